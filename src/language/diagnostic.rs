@@ -0,0 +1,158 @@
+use lexer::{Token, TokenPosition};
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Severity::Error   => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+// `len` columns starting at `pos`, on a single line
+#[derive(Debug, Clone, Copy)]
+pub struct Span {
+    pub pos: TokenPosition,
+    pub len: usize,
+}
+
+impl Span {
+    pub fn new(pos: TokenPosition, len: usize) -> Span {
+        Span {
+            pos: pos,
+            len: len,
+        }
+    }
+
+    pub fn of(token: &Token) -> Span {
+        Span::new(*token.pos(), token.content().chars().count())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span:    Span,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity:  Severity,
+    pub code:      &'static str,
+    pub message:   String,
+    pub primary:   Option<Label>,
+    pub secondary: Vec<Label>,
+    pub notes:     Vec<String>,
+}
+
+#[allow(dead_code)]
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, message: &str) -> Diagnostic {
+        Diagnostic {
+            severity:  severity,
+            code:      code,
+            message:   message.to_owned(),
+            primary:   None,
+            secondary: Vec::new(),
+            notes:     Vec::new(),
+        }
+    }
+
+    pub fn error(code: &'static str, message: &str) -> Diagnostic {
+        Diagnostic::new(Severity::Error, code, message)
+    }
+
+    pub fn warning(code: &'static str, message: &str) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, code, message)
+    }
+
+    // the span the diagnostic is about, underlined with carets
+    pub fn primary(mut self, span: Span, message: &str) -> Diagnostic {
+        self.primary = Some(Label { span: span, message: message.to_owned() });
+        self
+    }
+
+    // related spans, underlined with dashes
+    pub fn secondary(mut self, span: Span, message: &str) -> Diagnostic {
+        self.secondary.push(Label { span: span, message: message.to_owned() });
+        self
+    }
+
+    pub fn note(mut self, note: &str) -> Diagnostic {
+        self.notes.push(note.to_owned());
+        self
+    }
+
+    // e.g.
+    //
+    //   error[E0001]: unknown character escape '\q'
+    //    --> 3:12
+    //     |
+    //   3 | putsln("a\q")
+    //     |          ^
+    pub fn render(&self, source: &str) -> String {
+        let lines: Vec<&str> = source.lines().collect();
+
+        let mut labels: Vec<(&Label, char)> = Vec::new();
+
+        if let Some(ref l) = self.primary {
+            labels.push((l, '^'))
+        }
+
+        for l in &self.secondary {
+            labels.push((l, '-'))
+        }
+
+        let width  = labels.iter().map(|&(l, _)| l.span.pos.line.to_string().len()).max().unwrap_or(0);
+        let gutter = " ".repeat(width);
+
+        let mut out = format!("{}[{}]: {}\n", self.severity, self.code, self.message);
+
+        if let Some(ref l) = self.primary {
+            out.push_str(&format!("{}--> {}:{}\n", gutter, l.span.pos.line, l.span.pos.col + 1))
+        }
+
+        for (label, marker) in labels {
+            let line = match lines.get(label.span.pos.line.wrapping_sub(1)) {
+                Some(line) => line,
+                None       => continue,
+            };
+
+            // tabs are kept so the markers line up however they're displayed
+            let pad: String = line.chars()
+                                  .take(label.span.pos.col)
+                                  .map(|c| if c == '\t' { '\t' } else { ' ' })
+                                  .collect();
+
+            let markers: String = (0 .. label.span.len.max(1)).map(|_| marker).collect();
+
+            out.push_str(&format!("{} |\n", gutter));
+            out.push_str(&format!("{:>w$} | {}\n", label.span.pos.line, line, w = width));
+            out.push_str(&format!("{} | {}{} {}\n", gutter, pad, markers, label.message).trim_end());
+            out.push('\n')
+        }
+
+        for note in &self.notes {
+            out.push_str(&format!("{} = note: {}\n", gutter, note))
+        }
+
+        out
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.primary {
+            Some(ref l) => write!(f, "{}:{}: {}", l.span.pos.line, l.span.pos.col + 1, self.message),
+            None        => write!(f, "{}", self.message),
+        }
+    }
+}
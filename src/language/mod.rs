@@ -1,4 +1,5 @@
 pub mod syntax;
+pub mod diagnostic;
 
 #[macro_use]
 pub mod vm;
@@ -14,9 +15,11 @@ pub mod natives {
         native!("putsln", putsln, scope);
         native!("puts", puts, scope);
         native!("angry", angry, scope);
+        native!("len", len, scope);
+        native!("push", push, scope);
     }
 
-    fn putsln(args: Vec<Value>) -> Value {
+    fn putsln(args: Vec<Value>) -> Result<Value, String> {
         let s : Vec<String> = args.iter().map(
             |ref v| format!("{}", v)
         ).collect();
@@ -25,10 +28,10 @@ pub mod natives {
 
         println!("{}", joined);
 
-        Value::StringLiteral(joined)
+        Ok(Value::StringLiteral(joined))
     }
 
-    fn puts(args: Vec<Value>) -> Value {
+    fn puts(args: Vec<Value>) -> Result<Value, String> {
         let s : Vec<String> = args.iter().map(
             |ref v| format!("{}", v)
         ).collect();
@@ -37,41 +40,70 @@ pub mod natives {
 
         print!("{}", joined);
 
-        Value::StringLiteral(joined)
+        Ok(Value::StringLiteral(joined))
     }
 
-    fn angry(args: Vec<Value>) -> Value {
+    fn angry(args: Vec<Value>) -> Result<Value, String> {
         let s : Vec<String> = args.iter().map(
             |ref v| format!("{}", v)
         ).collect();
 
         panic!(s.join(" "));
 
-        Value::Nil
+        Ok(Value::Nil)
+    }
+
+    fn len(args: Vec<Value>) -> Result<Value, String> {
+        match args.get(0) {
+            Some(&Value::List(ref l))          => Ok(Value::IntLiteral(l.borrow().len() as i64)),
+            Some(&Value::StringLiteral(ref s)) => Ok(Value::IntLiteral(s.chars().count() as i64)),
+            Some(&Value::Map(ref m))           => Ok(Value::IntLiteral(m.borrow().len() as i64)),
+            Some(v) => Err(format!("can't take length of '{}'", v)),
+            None    => Err("len expects an argument".to_owned()),
+        }
+    }
+
+    fn push(args: Vec<Value>) -> Result<Value, String> {
+        let mut args = args.into_iter();
+
+        match args.next() {
+            Some(Value::List(l)) => {
+                l.borrow_mut().extend(args);
+                Ok(Value::Nil)
+            },
+            Some(v) => Err(format!("can't push to '{}'", v)),
+            None    => Err("push expects a list".to_owned()),
+        }
     }
 }
 
 pub mod compiler {
     use vm::{Op, Value, Object};
-    use syntax::parser::{Expression, Statement, Operand, Function};
+    use diagnostic::{Diagnostic, Span};
+    use syntax::parser::{Expression, Statement, Operand, Unary, Function};
 
     pub fn expression(script: &mut Vec<Op>, expr: &Expression) {
         match *expr {
             Expression::Atom(ref v) => script.push(Op::Value(v.clone())),
             Expression::Operation(ref l, ref op, ref r) => {
-                expression(script, r);
+                match *op {
+                    Operand::And => return logical(script, l, r, Op::JumpUnless, true),
+                    Operand::Or  => return logical(script, l, r, Op::JumpIf, false),
+                    Operand::Nor => return logical(script, l, r, Op::JumpIf, true),
+                    _            => (),
+                }
+
                 expression(script, l);
+                expression(script, r);
+
+                script.push(binary(op))
+            },
+            Expression::Unary(ref op, ref e) => {
+                expression(script, e);
                 match *op {
-                    Operand::Plus     => script.push(Op::Add),
-                    Operand::Minus    => script.push(Op::Sub),
-                    Operand::Mul      => script.push(Op::Mul),
-                    Operand::Div      => script.push(Op::Div),
-                    Operand::Equals   => script.push(Op::Equals),
-                    Operand::NEquals  => script.push(Op::NEquals),
-                    Operand::Lt       => script.push(Op::Lt),
-                    Operand::Gt       => script.push(Op::Gt),
-                    Operand::LtEquals => script.push(Op::LtEquals),
-                    Operand::GtEquals => script.push(Op::GtEquals),
+                    Unary::Neg => script.push(Op::Neg),
+                    Unary::Not => script.push(Op::Not),
+                    Unary::Complement => script.push(Op::Complement),
                 }
             },
             Expression::Identifier(ref n) => script.push(Op::Name(n.clone())),
@@ -83,22 +115,36 @@ pub mod compiler {
                 script.push(Op::Value(Value::IntLiteral((args.len() as i64) - 1)));
                 script.push(Op::Call)
             },
-            Expression::Function(Function {ref name, ref args, ref body}) => {
+            Expression::Function(ref function) => {
+                let Function {ref name, ref args, ref body} = *function;
+
                 let body = match body {
                     &Some(ref b) => statements(b.clone()),
                     &None    => vec!(Op::Value(Value::Nil)), // xd this is good haha
                 };
+
+                let names    = Names::of(function);
+                let upvalues = names.free();
+
                 let obj  = Object::Function {
                     args: args.clone(),
                     body,
+                    locals: names.declared.clone(),
+                    assigned: names.only_assigned(),
+                    upvalues: upvalues.clone(),
                 };
 
                 script.push(Op::Value(Value::Object(obj)));
 
+                if upvalues.len() > 0 {
+                    script.push(Op::Closure)
+                }
+
                 match *name {
                     Some(ref n) => {
                         script.push(Op::Value(Value::StringLiteral(n.clone())));
-                        script.push(Op::Define)
+                        script.push(Op::Define);
+                        script.push(Op::Name(n.clone()))
                     },
                     None => (),
                 }
@@ -111,53 +157,428 @@ pub mod compiler {
 
                 script.push(Op::Return)
             },
+            Expression::List(ref content) => {
+                for e in content {
+                    expression(script, e)
+                }
+
+                script.push(Op::List(content.len()))
+            },
+            Expression::Interpolation(ref parts) => {
+                for e in parts {
+                    expression(script, e)
+                }
+
+                script.push(Op::Interpolate(parts.len()))
+            },
+            Expression::Map(ref content) => {
+                for &(ref k, ref v) in content {
+                    expression(script, k);
+                    expression(script, v)
+                }
+
+                script.push(Op::Map(content.len()))
+            },
+            Expression::Index(ref target, ref index) => {
+                expression(script, target);
+                expression(script, index);
+
+                script.push(Op::Index)
+            },
+            Expression::If(ref branches, ref else_body) => {
+                let else_body = match *else_body {
+                    Some(ref b) => branch(b.clone()),
+                    None        => vec!(Op::Value(Value::Nil)),
+                };
+
+                conditional(script, branches.clone(), else_body, branch)
+            },
             _ => panic!("unimplemented expression!") ,
         }
     }
 
-    fn assignment(script: &mut Vec<Op>, name: &str, expr: &Expression) {
+    // built back to front, each taken branch jumps past the rest of the chain
+    fn conditional(script: &mut Vec<Op>, branches: Vec<(Expression, Vec<Statement>)>, else_body: Vec<Op>, compile: fn(Vec<Statement>) -> Vec<Op>) {
+        let mut chain = else_body;
+
+        for (cond, body) in branches.into_iter().rev() {
+            let body = compile(body);
+            let rest = chain;
+
+            chain = Vec::new();
+            expression(&mut chain, &cond);
+
+            if rest.len() > 0 {
+                chain.push(Op::JumpUnless(body.len() as i32 + 2));
+                chain.extend(body);
+                chain.push(Op::Jump(rest.len() as i32 + 1));
+                chain.extend(rest)
+            } else {
+                chain.push(Op::JumpUnless(body.len() as i32 + 1));
+                chain.extend(body)
+            }
+        }
+
+        script.extend(chain)
+    }
+
+    // a branch of an `if` expression, leaves the value of its last statement
+    fn branch(mut body: Vec<Statement>) -> Vec<Op> {
+        let last       = body.pop();
+        let mut script = statements(body);
+
+        match last {
+            Some(Statement::Expression(e)) => expression(&mut script, &e),
+            Some(s) => {
+                script.append(&mut statements(vec!(s)));
+                script.push(Op::Value(Value::Nil))
+            },
+            None => script.push(Op::Value(Value::Nil)),
+        }
+
+        script
+    }
+
+    // short-circuits to `!result` as soon as `jump` is taken for either side
+    fn logical(script: &mut Vec<Op>, l: &Expression, r: &Expression, jump: fn(i32) -> Op, result: bool) {
+        let mut right = Vec::new();
+        expression(&mut right, r);
+
+        expression(script, l);
+        script.push(jump(right.len() as i32 + 4));
+        script.extend(right);
+        script.push(jump(3));
+
+        script.push(Op::Value(Value::BoolLiteral(result)));
+        script.push(Op::Jump(2));
+        script.push(Op::Value(Value::BoolLiteral(!result)))
+    }
+
+    // resolves the `break`s and `continue`s of a loop body, targets are relative to
+    // the start of the body; nested loops have already resolved their own
+    fn loop_jumps(body: &mut Vec<Op>, break_target: i32, continue_target: i32) {
+        for (i, op) in body.iter_mut().enumerate() {
+            let i = i as i32;
+
+            match *op {
+                Op::Break(_)    => *op = Op::Unwind(break_target - i),
+                Op::Continue(_) => *op = Op::Unwind(continue_target - i),
+                _            => (),
+            }
+        }
+    }
+
+    fn binary(op: &Operand) -> Op {
+        match *op {
+            Operand::Plus     => Op::Add,
+            Operand::Minus    => Op::Sub,
+            Operand::Mul      => Op::Mul,
+            Operand::Div      => Op::Div,
+            Operand::IntDiv   => Op::IntDiv,
+            Operand::Mod      => Op::Mod,
+            Operand::Pow      => Op::Pow,
+            Operand::BitAnd   => Op::BitAnd,
+            Operand::BitOr    => Op::BitOr,
+            Operand::BitXor   => Op::BitXor,
+            Operand::Shl      => Op::Shl,
+            Operand::Shr      => Op::Shr,
+            Operand::Equals   => Op::Equals,
+            Operand::NEquals  => Op::NEquals,
+            Operand::Lt       => Op::Lt,
+            Operand::Gt       => Op::Gt,
+            Operand::LtEquals => Op::LtEquals,
+            Operand::GtEquals => Op::GtEquals,
+            Operand::Range    => Op::Range,
+            _                 => unreachable!(),
+        }
+    }
+
+    fn assignment(script: &mut Vec<Op>, name: &str, expr: &Expression, op: Op) {
         expression(script, expr);
 
         script.push(Op::Value(Value::StringLiteral(name.to_owned())));
-        script.push(Op::Define)
+        script.push(op)
+    }
+
+    fn insert(names: &mut Vec<String>, name: &str) {
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_owned())
+        }
+    }
+
+    // the names a function body introduces and uses, nested functions only
+    // contribute the names they need from outside
+    #[derive(Default)]
+    struct Names {
+        declared: Vec<String>,
+        assigned: Vec<String>,
+        used:     Vec<String>,
+    }
+
+    impl Names {
+        fn of(function: &Function) -> Names {
+            let mut names = Names::default();
+
+            for a in &function.args {
+                insert(&mut names.declared, a)
+            }
+
+            if let Some(ref body) = function.body {
+                names.statements(body)
+            }
+
+            names
+        }
+
+        // names that have to be captured from an enclosing function
+        fn free(&self) -> Vec<String> {
+            let mut free = Vec::new();
+
+            for n in self.used.iter().chain(self.assigned.iter()) {
+                if !self.declared.contains(n) {
+                    insert(&mut free, n)
+                }
+            }
+
+            free
+        }
+
+        // names a call only defines if they aren't globals already
+        fn only_assigned(&self) -> Vec<String> {
+            self.assigned.iter().filter(|n| !self.declared.contains(n)).cloned().collect()
+        }
+
+        fn statements(&mut self, stream: &[Statement]) {
+            for s in stream {
+                self.statement(s)
+            }
+        }
+
+        fn statement(&mut self, s: &Statement) {
+            match *s {
+                Statement::Expression(ref e)    => self.expression(e),
+                Statement::Block(ref ve)        => self.statements(ve),
+                Statement::Definition(ref n, ref e) => {
+                    insert(&mut self.declared, n);
+                    self.expression(e)
+                },
+                Statement::Assignment(ref n, ref e) => {
+                    insert(&mut self.assigned, n);
+                    self.expression(e)
+                },
+                Statement::IndexAssignment(ref target, ref index, ref value) |
+                Statement::CompoundIndexAssignment(ref target, ref index, _, ref value) => {
+                    self.expression(target);
+                    self.expression(index);
+                    self.expression(value)
+                },
+                Statement::CompoundAssignment(ref n, _, ref e) => {
+                    insert(&mut self.used, n);
+                    insert(&mut self.assigned, n);
+                    self.expression(e)
+                },
+                Statement::If(ref branches, ref else_body) => {
+                    for &(ref cond, ref body) in branches {
+                        self.expression(cond);
+                        self.statements(body)
+                    }
+
+                    if let Some(ref body) = *else_body {
+                        self.statements(body)
+                    }
+                },
+                Statement::While(ref cond, ref body) => {
+                    self.expression(cond);
+                    self.statements(body)
+                },
+                Statement::For(ref id, ref iterable, ref body) => {
+                    insert(&mut self.declared, id);
+                    self.expression(iterable);
+                    self.statements(body)
+                },
+                Statement::Break(_) | Statement::Continue(_) | Statement::Position(_) => (),
+            }
+        }
+
+        fn expression(&mut self, e: &Expression) {
+            match *e {
+                Expression::Atom(_)                   => (),
+                Expression::Identifier(ref n)         => insert(&mut self.used, n),
+                Expression::Unary(_, ref e)           => self.expression(e),
+                Expression::Operation(ref l, _, ref r) => {
+                    self.expression(l);
+                    self.expression(r)
+                },
+                Expression::Call(ref args) => for a in args.iter() {
+                    self.expression(a)
+                },
+                Expression::Function(ref f) => {
+                    if let Some(ref n) = f.name {
+                        insert(&mut self.declared, n)
+                    }
+
+                    for n in Names::of(f).free() {
+                        insert(&mut self.used, &n)
+                    }
+                },
+                Expression::Return(ref e) => if let Some(ref e) = *e {
+                    self.expression(e)
+                },
+                Expression::List(ref content) |
+                Expression::Interpolation(ref content) => for e in content {
+                    self.expression(e)
+                },
+                Expression::Map(ref content) => for &(ref k, ref v) in content {
+                    self.expression(k);
+                    self.expression(v)
+                },
+                Expression::Index(ref target, ref index) => {
+                    self.expression(target);
+                    self.expression(index)
+                },
+                Expression::If(ref branches, ref else_body) => {
+                    for &(ref cond, ref body) in branches {
+                        self.expression(cond);
+                        self.statements(body)
+                    }
+
+                    if let Some(ref body) = *else_body {
+                        self.statements(body)
+                    }
+                },
+            }
+        }
     }
 
     pub fn statements(stream: Vec<Statement>) -> Vec<Op> {
-        let mut script = Vec::new();
+        let mut script   = Vec::new();
+        let mut position = None;
 
         for s in stream {
             match s {
-                Statement::Expression(e)    => expression(&mut script, &e),
+                Statement::Position(pos) => {
+                    position = Some(pos);
+                    script.push(Op::Position(pos))
+                },
+                Statement::Expression(e)    => {
+                    expression(&mut script, &e);
+                    script.push(Op::Pop)
+                },
                 Statement::Block(ve)        => script.append(&mut statements(*ve)),
-                Statement::Definition(n, e) => assignment(&mut script, &n, &*e),
-                Statement::If(cond, body)   => {
+                Statement::Definition(n, e) => assignment(&mut script, &n, &*e, Op::Define),
+                Statement::If(branches, else_body) => {
+                    let else_body = else_body.map(statements).unwrap_or(Vec::new());
+                    conditional(&mut script, branches, else_body, statements)
+                },
+                Statement::While(cond, body) => {
+                    script.push(Op::Loop);
+
+                    let start = script.len();
+
+                    // the condition is checked again after the body, at the loop's own position
+                    if let Some(pos) = position {
+                        script.push(Op::Position(pos))
+                    }
+
                     expression(&mut script, &cond);
-                    let body = statements(*body);
 
-                    script.push(Op::JumpUnless(body.len() as i32 + 1));
-                    script.extend(body.iter().cloned());
+                    let mut body = statements(*body);
+                    let len      = body.len() as i32;
+                    let offset   = (script.len() - start) as i32 + 1;
+
+                    loop_jumps(&mut body, len + 1, -offset);
 
-                    expression(&mut script, &cond)
+                    script.push(Op::JumpUnless(len + 2));
+                    script.extend(body.iter().cloned());
+                    script.push(Op::Jump(-(offset + len)));
+                    script.push(Op::EndLoop)
                 },
-                Statement::IfElse(cond, body, else_body)   => {
-                    expression(&mut script, &cond);
-                    let body = statements(*body);
+                Statement::For(id, iterable, body) => {
+                    expression(&mut script, &iterable);
+                    script.push(Op::Iter);
+                    script.push(Op::Loop);
+
+                    let mut body = statements(*body);
+                    let len      = body.len() as i32;
+
+                    loop_jumps(&mut body, len + 1, -3);
 
-                    script.push(Op::JumpUnless(body.len() as i32 + 1));
+                    script.push(Op::IterNext(len + 4));
+                    script.push(Op::Value(Value::StringLiteral(id)));
+                    script.push(Op::Define);
                     script.extend(body.iter().cloned());
+                    script.push(Op::Jump(-(len + 3)));
+                    script.push(Op::IterDrop);
+                    script.push(Op::EndLoop)
+                },
+                Statement::Break(pos)    => script.push(Op::Break(pos)),
+                Statement::Continue(pos) => script.push(Op::Continue(pos)),
+                Statement::Assignment(ref id, ref expr) => assignment(&mut script, &id, &*expr, Op::Assign),
+                Statement::IndexAssignment(target, index, value) => {
+                    expression(&mut script, &target);
+                    expression(&mut script, &index);
+                    expression(&mut script, &value);
+
+                    script.push(Op::SetIndex)
+                },
+                Statement::CompoundAssignment(ref id, ref op, ref expr) => {
+                    script.push(Op::Name(id.clone()));
+                    expression(&mut script, expr);
+                    script.push(binary(op));
 
-                    expression(&mut script, &cond);
+                    script.push(Op::Value(Value::StringLiteral(id.clone())));
+                    script.push(Op::Assign)
+                },
+                Statement::CompoundIndexAssignment(target, index, op, value) => {
+                    expression(&mut script, &target);
+                    expression(&mut script, &index);
 
-                    let else_body = statements(*else_body);
+                    script.push(Op::Duplicate(2));
+                    script.push(Op::Index);
 
-                    script.push(Op::JumpIf(else_body.len() as i32 + 1));
-                    script.extend(else_body.iter().cloned())
+                    expression(&mut script, &value);
+                    script.push(binary(&op));
+
+                    script.push(Op::SetIndex)
                 },
-                Statement::Assignment(ref id, ref expr) => assignment(&mut script, &id, &*expr),
                 _ => panic!("unstable/unimplemented statement!?")
             }
         }
 
         script
     }
+
+    // a whole program, rejecting what can't run
+    pub fn compile(stream: Vec<Statement>) -> Result<Vec<Op>, Vec<Diagnostic>> {
+        let script = statements(stream);
+
+        let mut diagnostics = Vec::new();
+        loose_jumps(&script, &mut diagnostics);
+
+        if diagnostics.is_empty() {
+            Ok(script)
+        } else {
+            Err(diagnostics)
+        }
+    }
+
+    // `break` and `continue` that `loop_jumps` left, in the script or the functions it defines
+    fn loose_jumps(script: &[Op], diagnostics: &mut Vec<Diagnostic>) {
+        for op in script {
+            let (keyword, pos) = match *op {
+                Op::Break(pos)    => ("break", pos),
+                Op::Continue(pos) => ("continue", pos),
+                Op::Value(Value::Object(Object::Function { ref body, .. })) => {
+                    loose_jumps(body, diagnostics);
+                    continue
+                },
+                _ => continue,
+            };
+
+            let d = Diagnostic::error("E0200", &format!("'{}' outside of a loop", keyword));
+            let d = d.primary(Span::new(pos, keyword.len()), "not inside a loop");
+
+            diagnostics.push(d.note("only 'while' and 'for' bodies can be left early"))
+        }
+    }
 }
\ No newline at end of file
@@ -1,4 +1,6 @@
-use lexer::Token;
+use lexer::{lexer, Token, TokenType, TokenPosition};
+
+use diagnostic::{Diagnostic, Span};
 
 #[derive(Debug)]
 pub enum ChunkValue {
@@ -10,18 +12,25 @@ pub enum ChunkValue {
 #[derive(Debug)]
 pub struct Chunk {
     value: ChunkValue,
+    // where the chunk starts in the source
+    pos:   TokenPosition,
 }
 
 impl Chunk {
-    pub fn new(value: ChunkValue) -> Chunk {
+    pub fn new(value: ChunkValue, pos: TokenPosition) -> Chunk {
         Chunk {
             value: value,
+            pos:   pos,
         }
     }
 
     pub fn value(&self) -> &ChunkValue {
         &self.value
     }
+
+    pub fn pos(&self) -> &TokenPosition {
+        &self.pos
+    }
 }
 
 #[derive(Debug)]
@@ -41,6 +50,9 @@ impl Branch {
 pub struct BlockTree<'a> {
     source: &'a str,
     current_line: usize,
+    // columns a tab advances to the next multiple of, without one a tab is a
+    // single unit and can't be mixed with spaces
+    tab_width: Option<usize>,
 }
 
 #[allow(dead_code)]
@@ -49,57 +61,284 @@ impl<'a> BlockTree<'a> {
         BlockTree {
             source,
             current_line,
+            tab_width: None,
         }
     }
 
-    pub fn collect_indents(&self) -> Vec<(usize, &'a str)> {
+    pub fn tab_width(mut self, width: usize) -> BlockTree<'a> {
+        self.tab_width = Some(width);
+        self
+    }
+
+    // comment-only lines take the indentation of the code after them, so they
+    // stay in the token stream without opening or closing blocks
+    pub fn collect_indents(&self) -> Vec<(usize, TokenPosition, &'a str)> {
+        let lines = self.logical_lines();
+
         let mut indents = Vec::new();
-        let mut lines   = self.source.lines();
-        while let Some(line) = lines.next() {
-            let parts: Vec<&str> = line.split("#").collect();
-            let ln = parts.get(0).unwrap().trim();
-
-            if ln.len() > 0 {
-                let indent = self.indent(&line);
-                indents.push((indent, ln))
-            }
+        let mut last    = 0;
+
+        for (i, &(ws, pos, line, comment)) in lines.iter().enumerate() {
+            let indent = if comment {
+                lines[i ..].iter().find(|l| !l.3).map_or(last, |l| self.indent(l.0))
+            } else {
+                self.indent(ws)
+            };
+
+            last = indent;
+            indents.push((indent, pos, line))
         }
+
         indents
     }
 
+    // mixed tabs and spaces, dedents to levels that were never opened and indents
+    // after lines that can't take an indented body
+    pub fn validate(&self) -> Result<(), Vec<Diagnostic>> {
+        let mut errors = Vec::new();
+        let mut style  = None;
+        let mut levels = Vec::new();
+        let mut opens  = false;
+
+        for (ws, pos, line, comment) in self.logical_lines() {
+            if comment {
+                continue
+            }
+
+            let indent = self.indent(ws);
+            let span      = Span::new(TokenPosition::new(pos.line, 0), ws.chars().count());
+            let mut error = |e: &str| errors.push(Diagnostic::error("E0002", e).primary(span, ""));
+
+            if self.tab_width.is_none() {
+                if ws.contains(' ') && ws.contains('\t') {
+                    error("indentation mixes tabs and spaces")
+                } else if let Some(c) = ws.chars().next() {
+                    match style {
+                        None => style = Some(c),
+                        Some(s) if s != c => error(if c == '\t' {
+                            "indented with tabs, earlier lines use spaces"
+                        } else {
+                            "indented with spaces, earlier lines use tabs"
+                        }),
+                        _ => (),
+                    }
+                }
+            }
+
+            match levels.last().cloned() {
+                Some(top) if indent > top => {
+                    if !opens {
+                        error("unexpected indent")
+                    }
+                    levels.push(indent)
+                },
+                Some(top) if indent < top => {
+                    while levels.last().map_or(false, |&l| l > indent) {
+                        levels.pop();
+                    }
+
+                    if levels.last() != Some(&indent) {
+                        error("dedent doesn't match any outer indentation level");
+                        levels.push(indent)
+                    }
+                },
+                Some(_) => (),
+                None    => levels.push(indent),
+            }
+
+            opens = takes_body(line)
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    // the non-blank lines along with their indentation, position and whether
+    // they're only a comment, lines continued onto the following ones are joined
+    fn logical_lines(&self) -> Vec<(&'a str, TokenPosition, &'a str, bool)> {
+        let mut logical = Vec::new();
+        let mut lines   = self.lines().into_iter().enumerate();
+        while let Some((n, (start, line))) = lines.next() {
+            let (code, mut open) = scan(line, Open::default());
+
+            let ws = &line[.. line.len() - line.trim_start().len()];
+
+            // comment-only lines don't take part in the indentation
+            if line[.. code].trim().is_empty() {
+                if code < line.len() {
+                    let pos = TokenPosition::new(n + 1, ws.chars().count());
+                    logical.push((ws, pos, line.trim(), true))
+                }
+            } else {
+                let mut end  = start + line.len();
+                let mut last = &line[.. code];
+
+                // open brackets and `"""` strings, trailing operators and `\` continue
+                // through the following lines, blank and comment-only ones don't end them
+                while open.triple.is_some() || open.depth > 0 || continues(last) {
+                    match lines.next() {
+                        Some((_, (at, next))) => {
+                            let (code, next_open) = scan(next, open);
+
+                            open = next_open;
+                            end  = at + next.len();
+
+                            if !next[.. code].trim().is_empty() {
+                                last = &next[.. code]
+                            }
+                        },
+                        None => break,
+                    }
+                }
+
+                let pos = TokenPosition::new(n + 1, ws.chars().count());
+
+                logical.push((ws, pos, self.source[start + ws.len() .. end].trim_end(), false))
+            }
+        }
+        logical
+    }
+
+    // the lines of the source along with where they start
+    fn lines(&self) -> Vec<(usize, &'a str)> {
+        let mut lines  = Vec::new();
+        let mut offset = 0;
+        for line in self.source.split('\n') {
+            lines.push((offset, line.trim_end_matches('\r')));
+            offset += line.len() + 1
+        }
+        lines
+    }
+
     pub fn indent(&self, line: &str) -> usize {
         let mut pos: usize = 0;
         for c in line.chars() {
             match c {
-                ' ' | '\t' => {
-                    pos += 1
-                }
+                ' '  => pos += 1,
+                '\t' => pos = match self.tab_width {
+                    Some(w) if w > 0 => (pos / w + 1) * w,
+                    _                => pos + 1,
+                },
                 _ => break,
             }
         }
         pos
     }
 
-    pub fn tree(&mut self, indents: &Vec<(usize, &'a str)>) -> Branch {
+    pub fn tree(&mut self, indents: &Vec<(usize, TokenPosition, &'a str)>) -> Branch {
         let mut branch = Branch::new(Vec::new());
         let line       = indents.get(self.current_line);
-        let &(base_indent, _) = match line {
+        let &(base_indent, _, _) = match line {
             Some(i) => i,
             None    => return branch,
         };
 
         while self.current_line < indents.len() {
-            let (indent, line) = indents[self.current_line];
+            let (indent, pos, line) = indents[self.current_line];
             if indent == base_indent {
-                branch.value.push(Chunk::new(ChunkValue::Source(line.to_owned())))
+                branch.value.push(Chunk::new(ChunkValue::Source(line.to_owned()), pos))
             } else if indent < base_indent {
                 self.current_line -= 1;
                 return branch
             } else if indent > base_indent {
-                branch.value.push(Chunk::new(ChunkValue::Block(self.tree(&indents))))
+                branch.value.push(Chunk::new(ChunkValue::Block(self.tree(&indents)), pos))
             }
             self.current_line += 1
         }
         branch
     }
-}
\ No newline at end of file
+}
+
+// whether an indented body may follow the line, i.e. it has a keyword taking
+// one or ends in `->`, words in strings and comments don't count
+fn takes_body(line: &str) -> bool {
+    let tokens: Vec<Token> = lexer(&mut line.chars()).filter(|t| t.token_type != TokenType::Comment).collect();
+
+    let keyword = |t: &Token| t.token_type == TokenType::Keyword && ["fun", "if", "else", "while", "for"].contains(&t.content().as_str());
+
+    tokens.last().map_or(false, |t| t.content() == "->") || tokens.iter().any(keyword)
+}
+
+// what a line leaves open for the next one
+#[derive(Debug, Default, Clone, Copy)]
+struct Open {
+    // inside a `"""` string, and whether it's raw
+    triple: Option<bool>,
+    // unclosed brackets
+    depth:  usize,
+}
+
+// where the code of a line ends and a comment starts, and what it leaves open
+fn scan(line: &str, open: Open) -> (usize, Open) {
+    let chars: Vec<(usize, char)> = line.char_indices().collect();
+
+    let quotes = |i: usize| (i .. i + 3).all(|j| chars.get(j).map(|&(_, c)| c) == Some('"'));
+    let raw    = |i: usize| i > 0 && chars[i - 1].1 == 'r';
+
+    let mut open = open;
+    let mut i    = 0;
+
+    while i < chars.len() {
+        let (at, c) = chars[i];
+
+        if let Some(raw) = open.triple {
+            if quotes(i) {
+                open.triple = None;
+                i += 3
+            } else {
+                i += if c == '\\' && !raw { 2 } else { 1 }
+            }
+            continue
+        }
+
+        match c {
+            '#' => return (at, open),
+            '"' if quotes(i) => {
+                open.triple = Some(raw(i));
+                i += 3
+            },
+            '"' | '\'' => {
+                let raw = c == '"' && raw(i);
+
+                i += 1;
+                while i < chars.len() && chars[i].1 != c {
+                    i += if chars[i].1 == '\\' && !raw { 2 } else { 1 }
+                }
+                i += 1
+            },
+            '(' | '[' | '{' => {
+                open.depth += 1;
+                i += 1
+            },
+            ')' | ']' | '}' => {
+                open.depth = open.depth.saturating_sub(1);
+                i += 1
+            },
+            _ => i += 1,
+        }
+    }
+
+    (line.len(), open)
+}
+
+// whether the code of a line ends in an operator or `\` expecting more, `->` is
+// followed by an indented body instead
+fn continues(code: &str) -> bool {
+    let code = code.trim_end();
+
+    if code.ends_with("->") {
+        return false
+    }
+
+    if code.ends_with(|c| "\\+-*/%&|^<>=.,".contains(c)) {
+        return true
+    }
+
+    match code.rsplit(|c: char| !c.is_alphanumeric() && c != '_').next() {
+        Some("and") | Some("or") | Some("nor") => true,
+        _ => false,
+    }
+}
@@ -3,10 +3,19 @@ use lexer::matcher::*;
 use lexer::token::{Token, TokenType, TokenPosition};
 use lexer::block_tree::{ChunkValue, Branch, Chunk};
 
+use diagnostic::{Diagnostic, Span};
+
 use std::str::Chars;
 
 pub fn lexer(data: &mut Chars) -> Lexer {
-    let tokenizer = Tokenizer::new(data);
+    lexer_at(data, TokenPosition::default())
+}
+
+// tokens positioned as if `data` started at `pos` in the source
+pub fn lexer_at(data: &mut Chars, pos: TokenPosition) -> Lexer {
+    let mut tokenizer = Tokenizer::new(data);
+    tokenizer.pos = pos;
+
     let mut lexer = Lexer::new(tokenizer);
 
     let symbols = vec![
@@ -22,8 +31,8 @@ pub fn lexer(data: &mut Chars) -> Lexer {
         "}",
         "!",
         "~",
+        ";",
         "=",
-        "->",
     ].iter().map(|&x| x.to_string()).collect();
 
     let operators = vec![
@@ -31,12 +40,25 @@ pub fn lexer(data: &mut Chars) -> Lexer {
         "-",
         "*",
         "/",
+        "%",
+        "**",
+        "//",
+        "&",
+        "|",
+        "^",
+        "<<",
+        ">>",
         "==",
         "!=",
         "<",
         ">",
         ">=",
         "<=",
+        "..",
+        "+=",
+        "-=",
+        "*=",
+        "/=",
     ].iter().map(|&x| x.to_string()).collect();
 
     let keywords = vec![
@@ -48,6 +70,11 @@ pub fn lexer(data: &mut Chars) -> Lexer {
         "if",
         "else",
         "return",
+        "while",
+        "break",
+        "continue",
+        "for",
+        "in",
     ].iter().map(|&x| x.to_string()).collect();
 
     let boolean = vec![
@@ -56,22 +83,26 @@ pub fn lexer(data: &mut Chars) -> Lexer {
     ].iter().map(|&x| x.to_string()).collect();
 
     let matcher_symbol         = ConstantMatcher::new(TokenType::Symbol, symbols);
+    let matcher_arrow          = ConstantMatcher::new(TokenType::Symbol, vec!("->".to_string()));
     let matcher_operator       = ConstantMatcher::new(TokenType::Operator, operators);
     let matcher_keyword        = ConstantMatcher::new(TokenType::Keyword, keywords);
     let matcher_boolean        = ConstantMatcher::new(TokenType::BoolLiteral, boolean);
     let matcher_whitespace     = WhitespaceMatcher {};
+    let matcher_comment        = CommentMatcher {};
     let matcher_int_literal    = IntLiteralMatcher {};
     let matcher_float_literal  = FloatLiteralMatcher {};
     let matcher_identifier     = IdentifierMatcher {};
     let matcher_string_literal = StringLiteralMatcher {};
 
     lexer.matchers_mut().push(Box::new(matcher_whitespace));
+    lexer.matchers_mut().push(Box::new(matcher_comment));
+    lexer.matchers_mut().push(Box::new(matcher_int_literal)); // before "0" gets it
     lexer.matchers_mut().push(Box::new(matcher_float_literal));
-    lexer.matchers_mut().push(Box::new(matcher_int_literal));
     lexer.matchers_mut().push(Box::new(matcher_string_literal));
     lexer.matchers_mut().push(Box::new(matcher_boolean));
     lexer.matchers_mut().push(Box::new(matcher_keyword));
     lexer.matchers_mut().push(Box::new(matcher_identifier));
+    lexer.matchers_mut().push(Box::new(matcher_arrow)); // before "-" gets it
     lexer.matchers_mut().push(Box::new(matcher_operator));
     lexer.matchers_mut().push(Box::new(matcher_symbol));
     lexer
@@ -82,12 +113,12 @@ pub fn lex_branch(branch: &Branch) -> Branch {
     for c in branch.value.iter() {
         match c.value() {
             &ChunkValue::Source(ref s) => {
-                let chunk = ChunkValue::Tokens(lexer(&mut s.clone().chars()).collect());
-                lexed_branch.value.push(Chunk::new(chunk))
+                let chunk = ChunkValue::Tokens(lexer_at(&mut s.clone().chars(), *c.pos()).collect());
+                lexed_branch.value.push(Chunk::new(chunk, *c.pos()))
             },
             &ChunkValue::Block(ref b) => {
                 let chunk = ChunkValue::Block(lex_branch(&b));
-                lexed_branch.value.push(Chunk::new(chunk))
+                lexed_branch.value.push(Chunk::new(chunk, *c.pos()))
             },
             _ => (),
         }
@@ -100,7 +131,7 @@ pub fn flatten_branch(branch: &Branch) -> Vec<Token> {
     for c in branch.value.iter() {
         match c.value() {
             &ChunkValue::Tokens(ref t) => flat.append(&mut t.clone()),
-            &ChunkValue::Block(ref b)  => flat.push(Token::new(TokenType::Block(flatten_branch(b)), TokenPosition::new(0, 0), "".to_string())),
+            &ChunkValue::Block(ref b)  => flat.push(Token::new(TokenType::Block(flatten_branch(b)), *c.pos(), "".to_string())),
             _ => continue,
         }
     }
@@ -111,6 +142,26 @@ pub fn process_branch(branch: &Branch) -> Vec<Token> {
     flatten_branch(&lex_branch(branch))
 }
 
+// the malformed literals among the tokens, including those in blocks and interpolations
+pub fn diagnostics(tokens: &[Token]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for t in tokens {
+        match t.token_type {
+            TokenType::Error => {
+                let d = Diagnostic::error("E0001", t.content()).primary(Span::new(*t.pos(), 1), "");
+                diagnostics.push(d)
+            },
+            TokenType::Block(ref tokens) | TokenType::Interpolation(ref tokens) => {
+                diagnostics.append(&mut self::diagnostics(tokens))
+            },
+            _ => (),
+        }
+    }
+
+    diagnostics
+}
+
 pub struct Lexer {
     tokenizer: Tokenizer,
     matchers: Vec<Box<Matcher>>,
@@ -148,7 +199,17 @@ impl Iterator for Lexer {
     type Item = Token;
 
     fn next(&mut self) -> Option<Token> {
-        let token = self.match_token().unwrap();
+        let token = match self.match_token() {
+            Some(t) => t,
+            // nothing matches, skip the character so the rest is still lexed
+            None    => {
+                let pos = self.tokenizer.pos;
+                let c   = self.tokenizer.next().unwrap();
+
+                Token::new(TokenType::Error, pos, format!("unexpected character '{}'", c))
+            },
+        };
+
         match token.token_type {
             TokenType::EOF => None,
             TokenType::Whitespace => {
@@ -1,5 +1,5 @@
 use lexer::Tokenizer;
-use lexer::token::{Token, TokenType};
+use lexer::token::{Token, TokenType, TokenPosition};
 
 macro_rules! token {
     ($tokenizer:expr, $token_type:ident, $accum:expr) => {{
@@ -18,12 +18,37 @@ pub trait Matcher {
 
 pub struct WhitespaceMatcher;
 
+// a `\` ending the line, the block tree joins the next one to it
+fn continuation(tokenizer: &Tokenizer) -> Option<usize> {
+    if tokenizer.peek() != Some(&'\\') {
+        return None
+    }
+
+    let mut n = 1;
+    while let Some(&c) = tokenizer.peek_n(n) {
+        match c {
+            '\n'                   => return Some(n + 1),
+            '#'                    => return Some(n),
+            c if c.is_whitespace() => n += 1,
+            _                      => return None,
+        }
+    }
+
+    Some(n)
+}
+
 impl Matcher for WhitespaceMatcher {
     fn try_match(&self, tokenizer: &mut Tokenizer) -> Option<Token> {
         let mut found = false;
-        while !tokenizer.end() && tokenizer.peek().unwrap().is_whitespace() {
+        loop {
+            if !tokenizer.end() && tokenizer.peek().unwrap().is_whitespace() {
+                tokenizer.next();
+            } else if let Some(n) = continuation(tokenizer) {
+                tokenizer.advance(n)
+            } else {
+                break
+            }
             found = true;
-            tokenizer.next();
         }
         if found {
             token!(tokenizer, Whitespace, String::new())
@@ -33,58 +58,298 @@ impl Matcher for WhitespaceMatcher {
     }
 }
 
+// digits in the given radix, `_` may separate them
+fn digits(tokenizer: &mut Tokenizer, radix: u32) -> Result<String, String> {
+    let mut digits = String::new();
+
+    loop {
+        match tokenizer.peek() {
+            Some(&c) if c.is_digit(radix) => digits.push(c),
+            Some(&'_') if !digits.is_empty() && tokenizer.peek_n(1).map_or(false, |c| c.is_digit(radix)) => (),
+            Some(&'_') if !digits.is_empty() => return Err("'_' has to be between digits".to_owned()),
+            _ => return Ok(digits),
+        }
+
+        tokenizer.next();
+    }
+}
+
+fn number_error(tokenizer: &Tokenizer, result: Result<Option<Token>, String>) -> Option<Token> {
+    match result {
+        Ok(token) => token,
+        Err(e)    => Some(Token::new(TokenType::Error, tokenizer.last_position(), e)),
+    }
+}
+
+// `0x`, `0o` and `0b` literals, plain ones are left to the float matcher
 pub struct IntLiteralMatcher;
 
-impl Matcher for IntLiteralMatcher {
-    fn try_match(&self, tokenizer: &mut Tokenizer) -> Option<Token> {
-        let mut accum = String::new();
-        while !tokenizer.end() && tokenizer.peek().unwrap().is_digit(10) {
-            accum.push(tokenizer.next().unwrap());
+impl IntLiteralMatcher {
+    fn prefixed(tokenizer: &mut Tokenizer) -> Result<Option<Token>, String> {
+        if tokenizer.peek() != Some(&'0') {
+            return Ok(None)
         }
-        if !accum.is_empty() {
-            let literal: String = match u64::from_str_radix(accum.as_str(), 10) {
-                Ok(result) => result.to_string(),
-                Err(error) => panic!("Unable to parse integer literal: {}", error)
-            };
-            token!(tokenizer, IntLiteral, literal)
-        } else {
-            None
+
+        let (radix, name) = match tokenizer.peek_n(1) {
+            Some(&'x') => (16, "hex"),
+            Some(&'o') => (8, "octal"),
+            Some(&'b') => (2, "binary"),
+            _ => return Ok(None),
+        };
+
+        tokenizer.advance(2);
+
+        let accum = try!(digits(tokenizer, radix));
+
+        match tokenizer.peek() {
+            Some(&c) if c.is_alphanumeric() => return Err(format!("invalid {} digit '{}'", name, c)),
+            _ if accum.is_empty()           => return Err(format!("expected {} digits", name)),
+            _ => (),
         }
+
+        match i64::from_str_radix(&accum, radix) {
+            Ok(n)  => Ok(token!(tokenizer, IntLiteral, n.to_string())),
+            Err(_) => Err("integer literal is too large".to_owned()),
+        }
+    }
+}
+
+impl Matcher for IntLiteralMatcher {
+    fn try_match(&self, tokenizer: &mut Tokenizer) -> Option<Token> {
+        let result = IntLiteralMatcher::prefixed(tokenizer);
+        number_error(tokenizer, result)
     }
 }
 
+// decimal literals, integers unless there's a fraction or an exponent
 pub struct FloatLiteralMatcher;
 
+impl FloatLiteralMatcher {
+    fn decimal(tokenizer: &mut Tokenizer) -> Result<Option<Token>, String> {
+        let mut accum = try!(digits(tokenizer, 10));
+
+        let next_digit = |tokenizer: &Tokenizer, n| tokenizer.peek_n(n).map_or(false, |c| c.is_digit(10));
+
+        // `1..2` is a range
+        let fraction = tokenizer.peek() == Some(&'.') && tokenizer.peek_n(1) != Some(&'.')
+                    && (!accum.is_empty() || next_digit(tokenizer, 1));
+
+        if accum.is_empty() && !fraction {
+            return Ok(None)
+        }
+
+        let mut float = fraction;
+
+        if fraction {
+            tokenizer.next();
+
+            if accum.is_empty() {
+                accum.push('0')
+            }
+
+            accum.push('.');
+            accum.push_str(&try!(digits(tokenizer, 10)));
+
+            if tokenizer.peek() == Some(&'.') && tokenizer.peek_n(1) != Some(&'.') {
+                return Err("illegal decimal point".to_owned())
+            }
+        }
+
+        if tokenizer.peek() == Some(&'e') || tokenizer.peek() == Some(&'E') {
+            let sign = tokenizer.peek_n(1) == Some(&'-') || tokenizer.peek_n(1) == Some(&'+');
+            let at   = if sign { 2 } else { 1 };
+
+            if next_digit(tokenizer, at) {
+                tokenizer.next();
+                accum.push('e');
+
+                if sign {
+                    accum.push(tokenizer.next().unwrap())
+                }
+
+                accum.push_str(&try!(digits(tokenizer, 10)));
+                float = true
+            }
+        }
+
+        if float {
+            return Ok(token!(tokenizer, FloatLiteral, accum))
+        }
+
+        match accum.parse::<i64>() {
+            Ok(_)  => Ok(token!(tokenizer, IntLiteral, accum)),
+            Err(_) => Err("integer literal is too large".to_owned()),
+        }
+    }
+}
+
 impl Matcher for FloatLiteralMatcher {
     fn try_match(&self, tokenizer: &mut Tokenizer) -> Option<Token> {
-        let mut accum = String::new();
-        let curr = tokenizer.next().unwrap();
-        if curr.is_digit(10) {
-            accum.push(curr)
-        } else if curr == '.' {
-            accum.push_str("0.")
-        } else {
-            return None
-        }
-        while !tokenizer.end() {
-            let current = *tokenizer.peek().unwrap();
-            if !current.is_whitespace() && current.is_digit(10) || current == '.' {
-                if current == '.' && accum.contains('.') {
-                    panic!("illegal decimal point")
+        let result = FloatLiteralMatcher::decimal(tokenizer);
+        number_error(tokenizer, result)
+    }
+}
+
+// the code of an interpolated `{..}` up to its closing brace, braces inside
+// nested string and char literals don't count
+fn interpolation(tokenizer: &mut Tokenizer) -> Result<String, String> {
+    let mut code  = String::new();
+    let mut depth = 0;
+    let mut quote = None;
+
+    loop {
+        let c = match tokenizer.next() {
+            Some(c) => c,
+            None    => return Err("unterminated interpolation".to_owned()),
+        };
+
+        match quote {
+            Some(_) if c == '\\' => {
+                code.push(c);
+
+                if let Some(c) = tokenizer.next() {
+                    code.push(c)
                 }
-                accum.push(tokenizer.next().unwrap())
-            } else {
-                break
+
+                continue
+            },
+            Some(q) => if c == q {
+                quote = None
+            },
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '{'        => depth += 1,
+                '}' if depth == 0 => return Ok(code),
+                '}'        => depth -= 1,
+                _          => (),
+            },
+        }
+
+        code.push(c)
+    }
+}
+
+// the character escaped by a `\`, which is already consumed
+fn escape(tokenizer: &mut Tokenizer) -> Result<char, String> {
+    match tokenizer.next() {
+        Some(c @ '\\') | Some(c @ '\'') | Some(c @ '"') => Ok(c),
+        Some(c @ '{')  | Some(c @ '}')                  => Ok(c),
+        Some('n') => Ok('\n'),
+        Some('r') => Ok('\r'),
+        Some('t') => Ok('\t'),
+        Some('0') => Ok('\0'),
+        Some('x') => {
+            let digits = hex_digits(tokenizer, 2);
+
+            if digits.len() != 2 {
+                return Err("'\\x' escape needs two hex digits".to_owned())
+            }
+
+            match u32::from_str_radix(&digits, 16).unwrap() {
+                n if n <= 0x7f => Ok(n as u8 as char),
+                _ => Err(format!("'\\x{}' is out of range, use '\\u{{{}}}' above 7f", digits, digits)),
+            }
+        },
+        Some('u') => {
+            if tokenizer.peek() != Some(&'{') {
+                return Err("expected '{' after '\\u'".to_owned())
+            }
+
+            tokenizer.next();
+
+            let digits = hex_digits(tokenizer, 6);
+
+            if digits.is_empty() || tokenizer.peek() != Some(&'}') {
+                return Err("'\\u{..}' escape needs one to six hex digits".to_owned())
             }
+
+            tokenizer.next();
+
+            match ::std::char::from_u32(u32::from_str_radix(&digits, 16).unwrap()) {
+                Some(c) => Ok(c),
+                None    => Err(format!("'\\u{{{}}}' is not a unicode scalar value", digits)),
+            }
+        },
+        Some(c) => Err(format!("unknown character escape '\\{}'", c)),
+        None    => Err("unterminated escape".to_owned()),
+    }
+}
+
+fn hex_digits(tokenizer: &mut Tokenizer, max: usize) -> String {
+    let mut digits = String::new();
+
+    while digits.len() < max {
+        match tokenizer.peek() {
+            Some(&c) if c.is_digit(16) => digits.push(c),
+            _ => break,
         }
-        if accum.contains('.') {
-            token!(tokenizer, FloatLiteral, accum)
-        } else {
-            token!(tokenizer, IntLiteral, accum)
+
+        tokenizer.next();
+    }
+
+    digits
+}
+
+fn triple_quote(tokenizer: &Tokenizer, n: usize) -> bool {
+    (n .. n + 3).all(|i| tokenizer.peek_n(i) == Some(&'"'))
+}
+
+// looks ahead over a `"""` string for the indentation its lines have in common,
+// and whether the first and last lines are blank, those are dropped
+fn text_block(tokenizer: &Tokenizer, raw: bool) -> (usize, bool, bool) {
+    let mut lines = vec!(String::new());
+    let mut n     = 0;
+
+    while let Some(&c) = tokenizer.peek_n(n) {
+        if triple_quote(tokenizer, n) {
+            break
         }
+
+        match c {
+            '\\' if !raw => {
+                // an escaped quote doesn't close the string
+                lines.last_mut().unwrap().push(c);
+                n += 1
+            },
+            '\n' => {
+                lines.push(String::new());
+                n += 1;
+                continue
+            },
+            _ => (),
+        }
+
+        if let Some(&c) = tokenizer.peek_n(n) {
+            lines.last_mut().unwrap().push(c)
+        }
+
+        n += 1
     }
+
+    let blank = |l: &String| l.trim().is_empty();
+
+    let first_blank = lines.len() > 1 && blank(&lines[0]);
+    let last_blank  = lines.len() > 1 && blank(&lines[lines.len() - 1]);
+
+    let indent = lines[1 ..].iter()
+                            .filter(|l| !blank(l))
+                            .map(|l| l.chars().take_while(|c| c.is_whitespace()).count())
+                            .min()
+                            .unwrap_or(0);
+
+    (indent, first_blank, last_blank)
 }
 
+fn skip_indent(tokenizer: &mut Tokenizer, indent: usize) {
+    for _ in 0 .. indent {
+        match tokenizer.peek() {
+            Some(&c) if c != '\n' && c.is_whitespace() => tokenizer.next(),
+            _ => break,
+        };
+    }
+}
+
+// `"""` strings may span lines, the block tree keeps them in one chunk
 pub struct StringLiteralMatcher;
 
 impl Matcher for StringLiteralMatcher {
@@ -101,52 +366,114 @@ impl Matcher for StringLiteralMatcher {
             },
             _ => return None,
         };
-        tokenizer.advance(1); // Skips the opening delimeter
-        let mut string       = String::new();
-        let mut found_escape = false;
+        let triple = delimeter == Some('"') && triple_quote(tokenizer, 0);
+        tokenizer.advance(if triple { 3 } else { 1 }); // Skips the opening delimeter
+        let (indent, first_blank, last_blank) = if triple {
+            text_block(tokenizer, raw_marker)
+        } else {
+            (0, false, false)
+        };
+        if first_blank {
+            while tokenizer.next().map_or(false, |c| c != '\n') {}
+            skip_indent(tokenizer, indent)
+        }
+        let mut string = String::new();
+        let mut parts  = Vec::new();
+        // the first malformed escape or interpolation, the rest of the literal is still skipped
+        let mut error  = None;
         loop {
             if tokenizer.end() {
+                if error.is_none() {
+                    error = Some((tokenizer.last_position(), "unterminated literal".to_owned()))
+                }
+                break
+            }
+            if triple && triple_quote(tokenizer, 0) {
                 break
             }
+            if tokenizer.peek() == Some(&'\n') {
+                // skips the common indentation of the next line
+                string.push(tokenizer.next().unwrap());
+                skip_indent(tokenizer, indent);
+                continue
+            }
             if raw_marker {
-                if tokenizer.peek().unwrap() == &'"' {
+                if !triple && tokenizer.peek().unwrap() == &'"' {
                     break
                 }
                 string.push(tokenizer.next().unwrap())
             } else {
-                if found_escape {
-                    string.push(
-                        match tokenizer.next().unwrap() {
-                            c @ '\\' | c @ '\'' | c @ '"' => c,
-                            'n' => '\n',
-                            'r' => '\r',
-                            't' => '\t',
-                            s => panic!("unwanted character escape: {}", s),
+                let pos = tokenizer.pos;
+
+                match tokenizer.peek().unwrap() {
+                    &'\\' => {
+                        tokenizer.next();
+
+                        match escape(tokenizer) {
+                            Ok(c)  => string.push(c),
+                            Err(e) => if error.is_none() {
+                                error = Some((pos, e))
+                            },
                         }
-                    );
-                    found_escape = false
-                } else {
-                    match tokenizer.peek().unwrap() {
-                        &'\\' => {
-                            tokenizer.next();
-                            found_escape = true
-                        },
-                        &c if &c == &delimeter.unwrap() => break,
-                        _ => string.push(tokenizer.next().unwrap()),
-                    }
+                    },
+                    &c if !triple && &c == &delimeter.unwrap() => break,
+                    &'{' if delimeter == Some('"') => {
+                        tokenizer.next();
+
+                        let at   = TokenPosition::new(pos.line, pos.col + 1);
+                        let code = interpolation(tokenizer).and_then(|code| {
+                            let tokens: Vec<Token> = super::lexer_at(&mut code.chars(), at).collect();
+
+                            // nothing but whitespace and comments
+                            if tokens.iter().all(|t| t.token_type == TokenType::Comment) {
+                                Err("empty interpolation".to_owned())
+                            } else {
+                                Ok((code, tokens))
+                            }
+                        });
+
+                        match code {
+                            Ok((code, tokens)) => {
+                                parts.push(Token::new(TokenType::StringLiteral, pos, string.clone()));
+                                parts.push(Token::new(TokenType::Block(tokens), pos, code));
+                            },
+                            Err(e) => if error.is_none() {
+                                error = Some((pos, e))
+                            },
+                        }
+
+                        string.clear()
+                    },
+                    _ => string.push(tokenizer.next().unwrap()),
                 }
             }
         }
-        tokenizer.advance(1); // Skips the closing delimeter
+        if !tokenizer.end() {
+            tokenizer.advance(if triple { 3 } else { 1 }); // Skips the closing delimeter
+        }
+
+        if last_blank {
+            let end = string.rfind('\n').unwrap_or(0);
+            string.truncate(end)
+        }
+
+        if let Some((pos, e)) = error {
+            return Some(Token::new(TokenType::Error, pos, e))
+        }
+
         match delimeter.unwrap() {
-            '"'  => {
+            '"' if parts.is_empty() => {
                 token!(tokenizer, StringLiteral, string)
             },
+            '"' => {
+                parts.push(Token::new(TokenType::StringLiteral, tokenizer.last_position(), string));
+                token!(tokenizer, TokenType::Interpolation(parts), String::new())
+            },
             _ => {
-                if string.len() == 1 {
+                if string.chars().count() == 1 {
                     token!(tokenizer, CharLiteral, string)
                 } else {
-                    panic!("illegal char literal")
+                    Some(Token::new(TokenType::Error, tokenizer.last_position(), "a char literal holds exactly one character".to_owned()))
                 }
             },
         }
@@ -159,7 +486,10 @@ pub struct ConstantMatcher {
 }
 
 impl ConstantMatcher {
-    pub fn new(token_type: TokenType, constants: Vec<String>) -> Self {
+    pub fn new(token_type: TokenType, mut constants: Vec<String>) -> Self {
+        // longest first, so "<=" isn't lexed as "<" and "="
+        constants.sort_by(|a, b| b.len().cmp(&a.len()));
+
         ConstantMatcher {
             token_type: token_type,
             constants: constants,
@@ -175,6 +505,12 @@ impl Matcher for ConstantMatcher {
                 return None;
             }
             if dat.collect::<String>() == constant {
+                // words only match whole, "index" isn't "in" followed by "dex"
+                let word = constant.chars().last().map_or(false, |c| c.is_alphanumeric());
+                if word && tokenizer.peek_n(constant.len()).map_or(false, |&c| is_identifier(c)) {
+                    continue
+                }
+
                 tokenizer.advance(constant.len());
                 return token!(tokenizer, self.token_type.clone(), constant)
             }
@@ -183,8 +519,31 @@ impl Matcher for ConstantMatcher {
     }
 }
 
+// `#` up to the end of the line, kept as trivia
+pub struct CommentMatcher;
+
+impl Matcher for CommentMatcher {
+    fn try_match(&self, tokenizer: &mut Tokenizer) -> Option<Token> {
+        if tokenizer.peek() != Some(&'#') {
+            return None
+        }
+
+        tokenizer.next();
+
+        let mut accum = String::new();
+        while !tokenizer.end() && tokenizer.peek() != Some(&'\n') {
+            accum.push(tokenizer.next().unwrap());
+        }
+        token!(tokenizer, Comment, accum)
+    }
+}
+
 pub struct IdentifierMatcher;
 
+fn is_identifier(c: char) -> bool {
+    "_?!".contains(c) || c.is_alphanumeric()
+}
+
 impl Matcher for IdentifierMatcher {
     fn try_match(&self, tokenizer: &mut Tokenizer) -> Option<Token> {
         let mut identifier = String::new();
@@ -196,7 +555,7 @@ impl Matcher for IdentifierMatcher {
         }
         while !tokenizer.end() {
             let current = *tokenizer.peek().unwrap();
-            if !current.is_whitespace() && ("_?!".contains(current) || current.is_alphanumeric()) {
+            if !current.is_whitespace() && is_identifier(current) {
                 identifier.push(tokenizer.next().unwrap());
             } else {
                 break;
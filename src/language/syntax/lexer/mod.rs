@@ -8,4 +8,4 @@ mod lexer;
 pub use self::token::{Token, TokenType, TokenPosition};
 pub use self::matcher::Matcher;
 pub use self::tokenizer::Tokenizer;
-pub use self::lexer::{lexer, lex_branch, flatten_branch, process_branch};
\ No newline at end of file
+pub use self::lexer::{lexer, lexer_at, lex_branch, flatten_branch, process_branch, diagnostics};
\ No newline at end of file
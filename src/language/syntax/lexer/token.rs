@@ -1,6 +1,8 @@
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
     Block(Vec<Token>),
+    // `"a {b}"`, StringLiteral parts and Block parts holding the code
+    Interpolation(Vec<Token>),
     IntLiteral,
     FloatLiteral,
     StringLiteral,
@@ -11,6 +13,10 @@ pub enum TokenType {
     Identifier,
     Keyword,
     Whitespace,
+    // trivia, the parser skips it
+    Comment,
+    // a malformed literal, the content is the message
+    Error,
     EOF,
 }
 
@@ -1,13 +1,23 @@
 use super::super::super::Value;
 
+use lexer::TokenPosition;
+
 #[derive(Debug, Clone)]
 pub enum Expression {
     Atom(Value),
     Identifier(String),
     Operation(Box<Expression>, Operand, Box<Expression>),
+    Unary(Unary, Box<Expression>),
     Call(Box<Vec<Expression>>),
     Function(Function),
     Return(Option<Box<Expression>>),
+    List(Vec<Expression>),
+    Map(Vec<(Expression, Expression)>),
+    Index(Box<Expression>, Box<Expression>),
+    // `"a {b}"`, the parts are joined as strings
+    Interpolation(Vec<Expression>),
+    // the value of the taken branch's last statement, nil without one
+    If(Vec<(Expression, Vec<Statement>)>, Option<Vec<Statement>>),
 }
  
 #[derive(Debug, Clone)]
@@ -16,8 +26,18 @@ pub enum Statement {
     Definition(String, Box<Expression>),
     Expression(Box<Expression>),
     Assignment(String, Box<Expression>),
-    If(Box<Expression>, Box<Vec<Statement>>),
-    IfElse(Box<Expression>, Box<Vec<Statement>>, Box<Vec<Statement>>),
+    IndexAssignment(Box<Expression>, Box<Expression>, Box<Expression>),
+    // `a += b`, the target is only evaluated once
+    CompoundAssignment(String, Operand, Box<Expression>),
+    CompoundIndexAssignment(Box<Expression>, Box<Expression>, Operand, Box<Expression>),
+    // `if a .. else if b .. else ..`, the first branch whose condition holds is taken
+    If(Vec<(Expression, Vec<Statement>)>, Option<Vec<Statement>>),
+    While(Box<Expression>, Box<Vec<Statement>>),
+    For(String, Box<Expression>, Box<Vec<Statement>>),
+    Break(TokenPosition),
+    Continue(TokenPosition),
+    // where the following statement starts, runtime errors point at it
+    Position(TokenPosition),
 }
 
 #[derive(Debug, Clone)]
@@ -39,6 +59,14 @@ impl Function {
 pub enum Operand {
     Mul,
     Div,
+    Mod,
+    Pow,
+    IntDiv,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
     Plus,
     Minus,
     Equals,
@@ -47,20 +75,53 @@ pub enum Operand {
     Gt,
     LtEquals,
     GtEquals,
+    Range,
+    And,
+    Or,
+    Nor,
+}
+
+#[derive(Debug, Clone)]
+pub enum Unary {
+    Neg,
+    Not,
+    Complement,
 }
 
 pub fn operand(v: &str) -> Option<(Operand, u8)> {
     match v {
+        "**" => Some((Operand::Pow, 0)),
         "*"  => Some((Operand::Mul, 1)),
         "/"  => Some((Operand::Div, 1)),
+        "//" => Some((Operand::IntDiv, 1)),
+        "%"  => Some((Operand::Mod, 1)),
         "+"  => Some((Operand::Plus, 2)),
         "-"  => Some((Operand::Minus, 2)),
-        "==" => Some((Operand::Equals, 3)),
-        "!=" => Some((Operand::NEquals, 3)),
-        "<"  => Some((Operand::Lt, 4)),
-        ">"  => Some((Operand::Gt, 4)),
-        "<=" => Some((Operand::LtEquals, 4)),
-        ">=" => Some((Operand::GtEquals, 4)),
+        "<<" => Some((Operand::Shl, 3)),
+        ">>" => Some((Operand::Shr, 3)),
+        "&"  => Some((Operand::BitAnd, 4)),
+        "^"  => Some((Operand::BitXor, 5)),
+        "|"  => Some((Operand::BitOr, 6)),
+        ".." => Some((Operand::Range, 7)),
+        "==" => Some((Operand::Equals, 8)),
+        "!=" => Some((Operand::NEquals, 8)),
+        "<"  => Some((Operand::Lt, 9)),
+        ">"  => Some((Operand::Gt, 9)),
+        "<=" => Some((Operand::LtEquals, 9)),
+        ">=" => Some((Operand::GtEquals, 9)),
+        "and" => Some((Operand::And, 10)),
+        "or"  => Some((Operand::Or, 11)),
+        "nor" => Some((Operand::Nor, 11)),
+        _ => None,
+    }
+}
+
+pub fn compound(v: &str) -> Option<Operand> {
+    match v {
+        "+=" => Some(Operand::Plus),
+        "-=" => Some(Operand::Minus),
+        "*=" => Some(Operand::Mul),
+        "/=" => Some(Operand::Div),
         _ => None,
     }
-}
\ No newline at end of file
+}
@@ -1,16 +1,24 @@
 use super::super::Value;
 
+use diagnostic::{Diagnostic, Span};
+use lexer::{Token, TokenPosition};
+
+use std::mem;
+
 pub mod traveler;
 pub mod ast;
 
-pub use self::ast::{Expression, Statement, Function, Operand, operand};
+pub use self::ast::{Expression, Statement, Function, Operand, Unary, operand, compound};
 pub use self::traveler::Traveler;
 
 pub use super::lexer;
 pub use lexer::TokenType;
 
+pub type ParseError = Diagnostic;
+
 pub struct Parser {
     traveler: Traveler,
+    errors:   Vec<ParseError>,
 }
 
 #[allow(dead_code)]
@@ -18,293 +26,643 @@ impl Parser {
     pub fn new(traveler: Traveler) -> Parser {
         Parser {
             traveler: traveler,
+            errors:   Vec::new(),
         }
     }
 
-    pub fn parse(&mut self) -> Vec<Statement> {
+    // every statement, or every syntax error found skipping past broken statements
+    pub fn parse(&mut self) -> Result<Vec<Statement>, Vec<ParseError>> {
         let mut stack = Vec::new();
 
         while self.traveler.remaining() > 1 {
-            stack.push(self.statement());
+            if !self.symbol(";") {
+                let start = *self.traveler.current().pos();
+
+                match self.statement().and_then(|s| self.end_of_statement().map(|_| s)) {
+                    Ok(s)  => {
+                        stack.push(Statement::Position(start));
+                        stack.push(s)
+                    },
+                    Err(e) => {
+                        self.errors.push(e);
+                        self.synchronize(start)
+                    },
+                }
+            }
             self.traveler.next();
         }
 
-        stack
+        if self.errors.is_empty() {
+            Ok(stack)
+        } else {
+            Err(mem::replace(&mut self.errors, Vec::new()))
+        }
     }
 
-    #[allow(unused_must_use)]
-    fn statement(&mut self) -> Statement {
-        match self.traveler.current().token_type {
+    // statements end at a `;` or with their line
+    fn end_of_statement(&self) -> Result<(), ParseError> {
+        match self.traveler.peek(1) {
+            Some(t) if t.pos().line == self.traveler.current().pos().line && t.content() != ";" => {
+                let d = ParseError::error("E0100", &format!("expected end of statement, found '{}'", t.content()));
+                Err(d.primary(Span::of(t), "expected end of statement"))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    // skips what's left of a broken statement, up to a `;` or the next line
+    // starting at the indentation of the statement
+    fn synchronize(&mut self, start: TokenPosition) {
+        let boundary = |t: &Token| {
+            t.content() == ";" && t.token_type == TokenType::Symbol || t.pos().line > start.line && t.pos().col <= start.col
+        };
+
+        if self.traveler.remaining() > 1 && boundary(self.traveler.current()) {
+            self.traveler.prev();
+            return
+        }
+
+        loop {
+            match self.traveler.peek(1) {
+                Some(t) if !boundary(t) => (),
+                _ => return,
+            }
+
+            self.traveler.next();
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.traveler.remaining() <= 1
+    }
+
+    fn statement(&mut self) -> Result<Statement, ParseError> {
+        let statement = match self.traveler.current().token_type {
             TokenType::Identifier => {
-                let id = self.traveler.current_content();
+                let expr = try!(self.expression());
                 self.traveler.next();
                 match self.traveler.current_content().as_str() {
-                    "=" => {
+                    "=" if !self.at_end() => {
+                        let pos = *self.traveler.current().pos();
                         self.traveler.next();
-                        Statement::Assignment(id, Box::new(self.expression()))
+
+                        let value = Box::new(try!(self.expression()));
+
+                        match expr {
+                            Expression::Identifier(id)       => Statement::Assignment(id, value),
+                            Expression::Index(target, index) => Statement::IndexAssignment(target, index, value),
+                            _ => return Err(ParseError::error("E0100", "can't assign to expression").primary(Span::new(pos, 1), "")),
+                        }
                     },
-                    _   => {
-                        self.traveler.prev();
-                        Statement::Expression(Box::new(self.expression()))
+                    c   => match compound(c) {
+                        Some(op) if !self.at_end() => {
+                            let pos = *self.traveler.current().pos();
+                            self.traveler.next();
+
+                            let value = Box::new(try!(self.expression()));
+
+                            match expr {
+                                Expression::Identifier(id)       => Statement::CompoundAssignment(id, op, value),
+                                Expression::Index(target, index) => Statement::CompoundIndexAssignment(target, index, op, value),
+                                _ => return Err(ParseError::error("E0100", "can't assign to expression").primary(Span::new(pos, 2), "")),
+                            }
+                        },
+                        _ => {
+                            self.traveler.prev();
+                            Statement::Expression(Box::new(expr))
+                        },
                     },
                 }
             },
             TokenType::Keyword => match self.traveler.current_content().as_str() {
                 "var" => {
                     self.traveler.next();
-                    self.traveler.expect(TokenType::Identifier);
+                    try!(self.traveler.expect(TokenType::Identifier));
 
                     let id = self.traveler.current_content();
 
                     self.traveler.next();
 
-                    self.traveler.expect_content("=");
+                    try!(self.traveler.expect_content("="));
                     self.traveler.next();
 
-                    let expr = self.expression();
+                    let expr = try!(self.expression());
 
                     Statement::Definition(id, Box::new(expr))
                 },
 
                 "if" => {
+                    let (branches, else_body) = try!(self.conditional());
+                    Statement::If(branches, else_body)
+                },
+
+                "while" => {
                     self.traveler.next();
-                    let cond = self.expression();
+                    let cond = try!(self.expression());
                     self.traveler.next();
 
-                    let body = match self.traveler.current().token_type {
-                        TokenType::Block(_) => self.block(),
-                        _ => vec!(Statement::Expression(Box::new(self.expression()))),
-                    };
+                    let body = try!(self.body());
 
+                    Statement::While(Box::new(cond), Box::new(body))
+                },
+
+                "for" => {
                     self.traveler.next();
+                    try!(self.traveler.expect(TokenType::Identifier));
 
-                    if self.traveler.current_content() == "else" {
-                        self.traveler.next();
+                    let id = self.traveler.current_content();
 
-                        let else_body = match self.traveler.current().token_type {
-                            TokenType::Block(_) => self.block(),
-                            _ => vec!(Statement::Expression(Box::new(self.expression()))),
-                        };
+                    self.traveler.next();
 
-                        Statement::IfElse(Box::new(cond), Box::new(body), Box::new(else_body))
-                    } else {
-                        self.traveler.prev();
-                        Statement::If(Box::new(cond), Box::new(body))
-                    }
+                    try!(self.traveler.expect_content("in"));
+                    self.traveler.next();
+
+                    let iterable = try!(self.expression());
+                    self.traveler.next();
+
+                    let body = try!(self.body());
+
+                    Statement::For(id, Box::new(iterable), Box::new(body))
                 },
 
-                "fun" | "return" | "kill" => Statement::Expression(Box::new(self.expression())),
+                "break"    => Statement::Break(*self.traveler.current().pos()),
+                "continue" => Statement::Continue(*self.traveler.current().pos()),
 
-                k => panic!("very non-existing keyword: {}", k),
+                _ => Statement::Expression(Box::new(try!(self.expression()))),
             },
-            _ => Statement::Expression(Box::new(self.expression())),
+            _ => Statement::Expression(Box::new(try!(self.expression()))),
+        };
+
+        Ok(statement)
+    }
+
+    fn expression(&mut self) -> Result<Expression, ParseError> {
+        let expr = try!(self.atom());
+        self.operation(expr, u8::max_value())
+    }
+
+    // `if a .. else if b .. else ..`, starting at the first `if`
+    fn conditional(&mut self) -> Result<(Vec<(Expression, Vec<Statement>)>, Option<Vec<Statement>>), ParseError> {
+        let mut branches  = Vec::new();
+        let mut else_body = None;
+
+        loop {
+            self.traveler.next();
+            let cond = try!(self.expression());
+            self.traveler.next();
+
+            branches.push((cond, try!(self.body())));
+
+            self.traveler.next();
+
+            if self.keyword("else") && !self.at_end() {
+                self.traveler.next();
+
+                if self.keyword("if") {
+                    continue
+                }
+
+                else_body = Some(try!(self.body()));
+            } else {
+                self.traveler.prev();
+            }
+
+            return Ok((branches, else_body))
         }
     }
 
-    fn expression(&mut self) -> Expression {
-        let expr = self.atom();
-        self.traveler.next();
-        if self.traveler.remaining() > 0 {
-            if self.traveler.current().token_type == TokenType::Operator {
-                return self.operation(expr)
+    // an indented or braced block, or a single statement on the same line
+    fn body(&mut self) -> Result<Vec<Statement>, ParseError> {
+        if self.at_end() {
+            return Err(self.traveler.unexpected("expected body"))
+        }
+
+        match self.traveler.current().token_type {
+            TokenType::Block(_)       => self.block(),
+            _ if self.symbol("{")     => self.braces(),
+            _ => {
+                try!(self.inline());
+                Ok(vec!(try!(self.statement())))
+            },
+        }
+    }
+
+    // a body without a block has to start on the line of its header
+    fn inline(&self) -> Result<(), ParseError> {
+        match self.traveler.previous() {
+            Some(t) if self.new_line() => {
+                Err(ParseError::error("E0100", "expected body").primary(Span::of(t), "expected a body after this"))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    // the current token starts a different line than the one before it
+    fn new_line(&self) -> bool {
+        match self.traveler.previous() {
+            Some(t) => !self.at_end() && t.pos().line != self.traveler.current().pos().line,
+            None    => false,
+        }
+    }
+
+    fn keyword(&self, content: &str) -> bool {
+        self.traveler.current().token_type == TokenType::Keyword && self.traveler.current_content() == content
+    }
+
+    fn symbol(&self, content: &str) -> bool {
+        self.traveler.current().token_type == TokenType::Symbol && self.traveler.current_content() == content
+    }
+
+    // `{ .. }` in place of an indented block, parsed the same way
+    fn braces(&mut self) -> Result<Vec<Statement>, ParseError> {
+        let open = Span::of(self.traveler.current());
+
+        let mut tokens = Vec::new();
+        let mut depth  = 0;
+
+        loop {
+            self.traveler.next();
+
+            if self.at_end() {
+                return Err(self.traveler.unexpected("expected '}'").secondary(open, "unclosed block"))
             }
-            self.traveler.prev();
+
+            if self.symbol("{") {
+                depth += 1
+            } else if self.symbol("}") {
+                if depth == 0 {
+                    break
+                }
+                depth -= 1
+            }
+
+            tokens.push(self.traveler.current().clone())
         }
-        expr
+
+        Ok(self.nested(tokens))
     }
 
-    fn block(&mut self) -> Vec<Statement> {
+    fn block(&mut self) -> Result<Vec<Statement>, ParseError> {
         match self.traveler.current().token_type {
-            TokenType::Block(ref v) => {
-                let mut p = Parser::new(Traveler::new(v.clone()));
-                p.parse()
+            TokenType::Block(ref v) => Ok(self.nested(v.clone())),
+            _ => Err(self.traveler.unexpected("expected block")),
+        }
+    }
+
+    // the statements of a block, its errors are kept with the ones found here
+    // and parsing carries on after it
+    fn nested(&mut self, tokens: Vec<Token>) -> Vec<Statement> {
+        let mut p = Parser::new(Traveler::new(tokens));
+
+        match p.parse() {
+            Ok(statements) => statements,
+            Err(mut errors) => {
+                self.errors.append(&mut errors);
+                Vec::new()
             },
-            _ => panic!("expected block, found: {}", self.traveler.current_content())
         }
     }
 
-    #[allow(unused_must_use)]
-    fn atom(&mut self) -> Expression {
-        match self.traveler.current().token_type.clone() {
+    fn atom(&mut self) -> Result<Expression, ParseError> {
+        if self.at_end() {
+            return Err(self.traveler.unexpected("expected expression"))
+        }
+
+        let expr = match self.traveler.current().token_type.clone() {
             TokenType::IntLiteral    => Expression::Atom(Value::IntLiteral(self.traveler.current_content().parse::<i64>().unwrap())),
             TokenType::FloatLiteral  => Expression::Atom(Value::FloatLiteral(self.traveler.current_content().parse::<f64>().unwrap())),
             TokenType::BoolLiteral   => Expression::Atom(Value::BoolLiteral(self.traveler.current_content() == "yes")),
             TokenType::StringLiteral => Expression::Atom(Value::StringLiteral(self.traveler.current_content().clone())),
             TokenType::CharLiteral   => Expression::Atom(Value::CharLiteral(self.traveler.current_content().chars().nth(0).unwrap().clone())),
-            TokenType::Identifier    => {
-                let expr = Expression::Identifier(self.traveler.current_content());
-                
-                if self.traveler.next() {
-                    match self.traveler.current().token_type {
-                        TokenType::Operator => return self.operation(expr),
-                        TokenType::Symbol   => match self.traveler.current_content().as_str() {
-                            "(" => return self.call(expr),
-                            "~" => return Expression::Call(Box::new(vec!(expr))),
-                              ","
-                            | ")" => (),
-                            s   => panic!("unexpected symbol: {}", s),
+            TokenType::Interpolation(ref parts) => {
+                let mut stack = Vec::new();
+
+                for part in parts {
+                    match part.token_type {
+                        TokenType::Block(ref code) => {
+                            let mut p = Parser::new(Traveler::new(code.clone()));
+
+                            stack.push(try!(p.expression()));
+
+                            p.traveler.next();
+
+                            if !p.at_end() {
+                                return Err(p.traveler.unexpected("expected end of interpolation"))
+                            }
                         },
-                        _ => (),
+                        _ => stack.push(Expression::Atom(Value::StringLiteral(part.content().clone()))),
                     }
                 }
 
-                self.traveler.prev();
+                Expression::Interpolation(stack)
+            },
+            TokenType::Identifier    => {
+                let expr = Expression::Identifier(self.traveler.current_content());
 
-                expr
+                try!(self.postfix(expr))
+            },
+            TokenType::Operator if self.traveler.current_content() == "-" => {
+                self.traveler.next();
+                // `-a ** b` is `-(a ** b)`
+                let operand = try!(self.atom());
+                let operand = try!(self.operation(operand, 1));
+
+                Expression::Unary(Unary::Neg, Box::new(operand))
             },
             TokenType::Symbol => match self.traveler.current_content().as_str() {
-                "(" => {
+                "!" => {
                     self.traveler.next();
-                    let expr = self.expression();
+                    Expression::Unary(Unary::Not, Box::new(try!(self.atom())))
+                },
+                // prefix `~` is the complement, after an expression it's a call and
+                // `return~` is always a bare return, `return (~a)` returns the complement
+                "~" => {
                     self.traveler.next();
 
-                    self.traveler.expect_content(")");
+                    let operand = try!(self.atom());
+                    let operand = try!(self.operation(operand, 1));
+
+                    Expression::Unary(Unary::Complement, Box::new(operand))
+                },
+                "(" if self.lambda_ahead() => try!(self.lambda()),
+                "(" => {
+                    let open = Span::of(self.traveler.current());
 
+                    self.traveler.next();
+                    let expr = try!(self.expression());
                     self.traveler.next();
 
-                    if self.traveler.current_content() == "(" {
-                        return self.call(expr)
-                    }
+                    try!(self.traveler.expect_content(")").map_err(|d| d.secondary(open, "to close this")));
 
-                    self.traveler.prev();
-                    expr
+                    try!(self.postfix(expr))
                 },
-                s => panic!("very unexpected symbol: {}", s),
+                "[" => {
+                    let list = try!(self.list("]", |p| p.expression()));
+                    try!(self.postfix(Expression::List(list)))
+                },
+                "{" => {
+                    let map = try!(self.list("}", |p| {
+                        let key = try!(p.expression());
+
+                        p.traveler.next();
+                        try!(p.traveler.expect_content(":"));
+                        p.traveler.next();
+
+                        Ok((key, try!(p.expression())))
+                    }));
+
+                    try!(self.postfix(Expression::Map(map)))
+                },
+                _ => return Err(self.traveler.unexpected("expected expression")),
             },
             TokenType::Keyword => match self.traveler.current_content().as_str() {
                 "fun" => {
                     self.traveler.next();
                     let name: Option<String>;
 
-                    if self.traveler.current().token_type == TokenType::Identifier {
+                    if self.traveler.current().token_type == TokenType::Identifier && !self.at_end() {
                         name = Some(self.traveler.current_content());
                         self.traveler.next();
                     } else {
                         name = None
                     }
 
-                    let mut body: Option<Vec<Statement>> = None;
-
                     let mut args = Vec::new();
 
-                    match self.traveler.current().token_type.clone() {
-                        TokenType::Block(_) => body = Some(self.block()),
-                        TokenType::Symbol   => match self.traveler.current_content().as_str() {
-                            "(" => {
-                                self.traveler.next();
-                                while self.traveler.current().token_type == TokenType::Identifier {
-                                    args.push(self.traveler.current_content());
-                                    self.traveler.next();
-
-                                    if self.traveler.current_content() == "," {
-                                        self.traveler.next();
-                                    }
-                                }
-                                self.traveler.expect_content(")");
-                                self.traveler.next();
-
-                                match self.traveler.current().token_type {
-                                    TokenType::Block(_) => {
-                                        body = Some(self.block());
-                                    },
-                                    _ => body = Some(vec!(Statement::Expression(Box::new(self.expression())))),
-                                }
-                            },
-                            _ => panic!("unexpected symbol: {}", self.traveler.current_content()),
-                        },
-                        t => body = Some(vec!(Statement::Expression(Box::new(self.expression())))),
+                    if self.symbol("(") && !self.at_end() {
+                        self.traveler.next();
+                        args = try!(self.params());
+                        self.traveler.next();
                     }
 
-                    Expression::Function(Function::new(name, args, body))
+                    let body = match self.traveler.current().token_type {
+                        _ if self.at_end()    => return Err(self.traveler.unexpected("expected function body")),
+                        TokenType::Block(_)   => try!(self.block()),
+                        _ if self.symbol("{") => try!(self.braces()),
+                        _ => {
+                            try!(self.inline());
+                            vec!(Statement::Expression(Box::new(try!(self.expression()))))
+                        },
+                    };
+
+                    Expression::Function(Function::new(name, args, Some(body)))
+                },
+
+                "if" => {
+                    let (branches, else_body) = try!(self.conditional());
+                    Expression::If(branches, else_body)
                 },
 
                 "return" => {
                     self.traveler.next();
-                    if self.traveler.current_content() == "~" {
+                    if self.traveler.current_content() == "~" || self.at_end() || self.new_line() {
                         Expression::Return(None)
                     } else {
-                        Expression::Return(Some(Box::new(self.expression())))
+                        Expression::Return(Some(Box::new(try!(self.expression()))))
                     }
                 },
 
-                _ => panic!("unexpected keyword: {}", self.traveler.current_content()),
+                _ => return Err(self.traveler.unexpected("expected expression")),
             },
-            _ => panic!("very unexpected: '{}'", self.traveler.current_content()),
+            TokenType::Error => {
+                let d = ParseError::error("E0001", &self.traveler.current_content());
+                return Err(d.primary(Span::new(*self.traveler.current().pos(), 1), ""))
+            },
+            _ => return Err(self.traveler.unexpected("expected expression")),
+        };
+
+        Ok(expr)
+    }
+
+    // `a, b)` of a parameter list, ending at the `)`
+    fn params(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut args = Vec::new();
+
+        while self.traveler.current().token_type == TokenType::Identifier && !self.at_end() {
+            args.push(self.traveler.current_content());
+            self.traveler.next();
+
+            if self.symbol(",") {
+                self.traveler.next();
+            }
         }
+
+        if self.at_end() {
+            return Err(self.traveler.unexpected("expected ')'"))
+        }
+
+        try!(self.traveler.expect_content(")"));
+
+        Ok(args)
     }
 
-    fn call(&mut self, expr: Expression) -> Expression {
+    // comma separated items up to `close`, starting at the opening bracket
+    fn list<T, F>(&mut self, close: &str, item: F) -> Result<Vec<T>, ParseError>
+        where F: Fn(&mut Parser) -> Result<T, ParseError>
+    {
+        let open = Span::of(self.traveler.current());
+
         self.traveler.next();
 
-        let mut stack = vec!(expr);
+        let mut stack = Vec::new();
+
+        loop {
+            if self.at_end() {
+                let d = self.traveler.unexpected(&format!("expected '{}'", close));
+                return Err(d.secondary(open, "unclosed bracket"))
+            }
+
+            if self.symbol(close) {
+                return Ok(stack)
+            }
 
-        while self.traveler.current_content() != ")" {
-            stack.push(self.expression());
+            stack.push(try!(item(self)));
 
             self.traveler.next();
 
-            if self.traveler.current_content() == "," {
+            if self.symbol(",") {
                 self.traveler.next();
+            } else if !self.symbol(close) && !self.at_end() {
+                let d = self.traveler.unexpected(&format!("expected ',' or '{}'", close));
+                return Err(d.secondary(open, "to close this"))
             }
         }
+    }
+
+    // `(a, b) ->` rather than a parenthesized expression
+    fn lambda_ahead(&self) -> bool {
+        let content = |n| self.traveler.peek(n).map(|t| t.content().clone()).unwrap_or(String::new());
+
+        let mut n = 1;
+
+        if content(n) != ")" {
+            loop {
+                match self.traveler.peek(n) {
+                    Some(t) if t.token_type == TokenType::Identifier => (),
+                    _ => return false,
+                }
+
+                n += 1;
 
-        Expression::Call(Box::new(stack))
+                match content(n).as_str() {
+                    "," => n += 1,
+                    ")" => break,
+                    _   => return false,
+                }
+            }
+        }
+
+        content(n + 1) == "->"
     }
 
-    fn operation(&mut self, expression: Expression) -> Expression {
-        let mut ex_stack = vec![expression];
-        let mut op_stack: Vec<(Operand, u8)> = Vec::new();
+    fn lambda(&mut self) -> Result<Expression, ParseError> {
+        self.traveler.next();
 
-        op_stack.push(operand(&self.traveler.current_content()).unwrap());
+        let args = try!(self.params());
 
+        self.traveler.next();
+        try!(self.traveler.expect_content("->"));
         self.traveler.next();
 
-        ex_stack.push(self.atom());
+        let body = match self.traveler.current().token_type {
+            _ if self.at_end()    => return Err(self.traveler.unexpected("expected lambda body")),
+            TokenType::Block(_)   => try!(self.block()),
+            _ if self.symbol("{") => try!(self.braces()),
+            _ => {
+                try!(self.inline());
 
-        let mut done = false;
-        while ex_stack.len() > 1 {
+                let value = Expression::Return(Some(Box::new(try!(self.expression()))));
+                vec!(Statement::Expression(Box::new(value)))
+            },
+        };
 
-            if !done && self.traveler.next() {
-                if self.traveler.current().token_type != TokenType::Operator {
-                    self.traveler.prev();
+        Ok(Expression::Function(Function::new(None, args, Some(body))))
+    }
 
-                    done = true;
+    // calls and indexing following an expression, e.g. `f(a)[0](b)`
+    fn postfix(&mut self, expr: Expression) -> Result<Expression, ParseError> {
+        let mut expr = expr;
 
-                    continue
+        loop {
+            self.traveler.next();
+
+            if self.traveler.remaining() > 1 && self.traveler.current().token_type == TokenType::Symbol {
+                match self.traveler.current_content().as_str() {
+                    "(" => { expr = try!(self.call(expr)); continue },
+                    "[" => { expr = try!(self.index(expr)); continue },
+                    "~" => { expr = Expression::Call(Box::new(vec!(expr))); continue },
+                    _   => (),
                 }
+            }
 
-                let (op, prec) = operand(&self.traveler.current_content()).unwrap();
+            self.traveler.prev();
+
+            return Ok(expr)
+        }
+    }
 
-                if prec > op_stack.last().unwrap().1 {
-                    let left = ex_stack.pop().unwrap();
-                    let right = ex_stack.pop().unwrap();
+    fn index(&mut self, expr: Expression) -> Result<Expression, ParseError> {
+        self.traveler.next();
 
-                    ex_stack.push(Expression::Operation(Box::new(left),
-                                                        op_stack.pop().unwrap().0,
-                                                        Box::new(right)));
+        let index = try!(self.expression());
 
-                    self.traveler.next();
+        self.traveler.next();
 
-                    ex_stack.push(self.atom());
-                    op_stack.push((op, prec));
+        if self.at_end() {
+            return Err(self.traveler.unexpected("expected ']'"))
+        }
 
-                    continue
-                }
+        try!(self.traveler.expect_content("]"));
 
-                self.traveler.next();
+        Ok(Expression::Index(Box::new(expr), Box::new(index)))
+    }
+
+    fn call(&mut self, expr: Expression) -> Result<Expression, ParseError> {
+        let mut stack = vec!(expr);
+
+        stack.append(&mut try!(self.list(")", |p| p.expression())));
+
+        Ok(Expression::Call(Box::new(stack)))
+    }
+
+    // binary operations binding tighter than `limit`, e.g. `a - b - c` is `(a - b) - c`
+    fn operation(&mut self, expression: Expression, limit: u8) -> Result<Expression, ParseError> {
+        let mut left = expression;
+
+        loop {
+            self.traveler.next();
+
+            if self.traveler.remaining() > 1 {
+                if let Some((op, prec)) = self.operand() {
+                    if prec < limit {
+                        self.traveler.next();
+
+                        // `**` is right-associative, `a ** b ** c` is `a ** (b ** c)`
+                        let right_limit = match op {
+                            Operand::Pow => prec + 1,
+                            _            => prec,
+                        };
+
+                        let right = try!(self.atom());
+                        let right = try!(self.operation(right, right_limit));
+
+                        left = Expression::Operation(Box::new(left), op, Box::new(right));
 
-                ex_stack.push(self.atom());
-                op_stack.push((op, prec));
+                        continue
+                    }
+                }
             }
 
-            let left = ex_stack.pop().unwrap();
-            let right = ex_stack.pop().unwrap();
+            self.traveler.prev();
 
-            ex_stack.push(Expression::Operation(Box::new(left),
-                                                op_stack.pop().unwrap().0,
-                                                Box::new(right)));
+            return Ok(left)
         }
+    }
 
-        ex_stack.pop().unwrap()
+    fn operand(&self) -> Option<(Operand, u8)> {
+        match self.traveler.current().token_type {
+            TokenType::Operator | TokenType::Keyword => operand(&self.traveler.current_content()),
+            _ => None,
+        }
     }
 }
\ No newline at end of file
@@ -1,17 +1,22 @@
-use lexer::{Token, TokenType};
+use lexer::{Token, TokenType, TokenPosition};
+
+use diagnostic::{Diagnostic, Span};
 
 #[derive(Debug, Clone)]
 pub struct Traveler {
     pub tokens: Vec<Token>,
     top: usize,
+    // what `current` gives when there are no tokens at all
+    eof: Token,
 }
 
 #[allow(dead_code)]
 impl<'a> Traveler {
     pub fn new(tokens: Vec<Token>) -> Traveler {
         Traveler {
-            tokens: tokens,
+            tokens: tokens.into_iter().filter(|t| t.token_type != TokenType::Comment).collect(),
             top: 0,
+            eof: Token::new(TokenType::EOF, TokenPosition::default(), String::new()),
         }
     }
 
@@ -36,10 +41,10 @@ impl<'a> Traveler {
     }
 
     pub fn current(&self) -> &Token {
-        if self.top > self.tokens.len() - 1 {
-            return &self.tokens[self.tokens.len() - 1];
+        match self.tokens.get(self.top).or(self.tokens.last()) {
+            Some(t) => t,
+            None    => &self.eof,
         }
-        &self.tokens[self.top]
     }
 
     pub fn get(&self, i: usize) -> &Token {
@@ -47,38 +52,53 @@ impl<'a> Traveler {
         &self.tokens[i]
     }
 
+    pub fn peek(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.top + n)
+    }
+
+    // the token before the current one
+    pub fn previous(&self) -> Option<&Token> {
+        if self.top == 0 {
+            return None
+        }
+        self.tokens.get(self.top - 1)
+    }
+
     pub fn current_content(&self) -> String {
         self.current().content().clone()
     }
 
-    pub fn expect(&self, token: TokenType) -> Result<&Token, String> {
+    pub fn expect(&self, token: TokenType) -> Result<&Token, Diagnostic> {
         if self.current().token_type == token {
             Ok(self.current())
         } else {
-            Err(format!("expected '{:?}', found '{:?}'", token, self.current_content()))
+            Err(self.unexpected(&format!("expected {:?}", token)))
         }
     }
 
-    pub fn expect_content(&self, content: &str) -> Result<&Token, String> {
+    pub fn expect_content(&self, content: &str) -> Result<&Token, Diagnostic> {
         if &self.current_content() == content {
             Ok(self.current())
         } else {
-            Err(format!("expected '{}', found '{:#?}'", content, self.current()))
+            Err(self.unexpected(&format!("expected '{}'", content)))
         }
     }
 
-    pub fn expecte_contents(&self, sequence: Vec<String>) -> Result<Vec<&Token>, String> {
+    pub fn expecte_contents(&self, sequence: Vec<String>) -> Result<Vec<&Token>, Diagnostic> {
         let mut accum: usize = 0;
 
         let mut res = Vec::new();
 
         for c in sequence {
             if self.top + accum >= self.tokens.len() {
-                return Err(format!("expected '{}', found end of source >:(", c))
+                return Err(Diagnostic::error("E0100", &format!("expected '{}', found end of source >:(", c)))
             }
 
             if &c != self.tokens[self.top + accum].content() {
-                return Err(format!("expected '{}', found '{}'", c, self.tokens[self.top + accum].content()))
+                let found = &self.tokens[self.top + accum];
+                let d     = Diagnostic::error("E0100", &format!("expected '{}', found '{}'", c, found.content()));
+
+                return Err(d.primary(Span::of(found), &format!("expected '{}'", c)))
             }
 
             res.push(self.get(self.top + accum));
@@ -88,4 +108,15 @@ impl<'a> Traveler {
 
         Ok(res)
     }
-}
\ No newline at end of file
+
+    // a syntax error at the current token
+    pub fn unexpected(&self, label: &str) -> Diagnostic {
+        if self.top >= self.tokens.len() {
+            let d = Diagnostic::error("E0100", &format!("{}, found end of source", label));
+            return d.primary(Span::of(self.current()), label)
+        }
+
+        let d = Diagnostic::error("E0100", &format!("{}, found '{}'", label, self.current_content()));
+        d.primary(Span::of(self.current()), label)
+    }
+}
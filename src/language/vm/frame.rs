@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use vm::{Value, Upvalue};
+
+#[derive(Debug, Clone)]
+pub struct Frame {
+    locals:   HashMap<String, Upvalue>,
+    upvalues: HashMap<String, Upvalue>,
+    declared: Vec<String>,
+    assigned: Vec<String>,
+}
+
+impl Frame {
+    pub fn new(upvalues: HashMap<String, Upvalue>, declared: Vec<String>, assigned: Vec<String>) -> Frame {
+        Frame {
+            locals: HashMap::new(),
+            upvalues,
+            declared,
+            assigned,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.cell(name).map(|c| c.borrow().clone())
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.cell(name).is_some()
+    }
+
+    fn cell(&self, name: &str) -> Option<&Upvalue> {
+        self.locals.get(name).or_else(|| self.upvalues.get(name))
+    }
+
+    // `var`, always introduces a local
+    pub fn define(&mut self, name: String, value: Value) {
+        if let Some(c) = self.locals.get(&name) {
+            *c.borrow_mut() = value;
+            return
+        }
+
+        self.locals.insert(name, Rc::new(RefCell::new(value)));
+    }
+
+    // plain assignment, writes through captured upvalues
+    pub fn assign(&mut self, name: String, value: Value) {
+        if let Some(c) = self.cell(&name) {
+            *c.borrow_mut() = value;
+            return
+        }
+
+        self.define(name, value)
+    }
+
+    // names this frame will only define later are captured as empty cells, so the
+    // closure sees e.g. a local helper function defined after it. a name that is
+    // only assigned stays a global if one exists, assignment writes that instead
+    pub fn capture(&mut self, name: &str, globals: &HashMap<String, Value>) -> Option<Upvalue> {
+        if let Some(c) = self.cell(name) {
+            return Some(c.clone())
+        }
+
+        let local = self.declared.iter().any(|d| d == name)
+                 || self.assigned.iter().any(|a| a == name) && !globals.contains_key(name);
+
+        if local {
+            self.define(name.to_owned(), Value::Nil);
+            return self.locals.get(name).cloned()
+        }
+
+        None
+    }
+}
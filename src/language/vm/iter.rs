@@ -0,0 +1,27 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use vm::Value;
+
+#[derive(Debug, Clone)]
+pub enum Iter {
+    Range(i64, i64),
+    Chars(Vec<char>, usize),
+    List(Rc<RefCell<Vec<Value>>>, usize),
+    Keys(Vec<Value>, usize),
+    // anything callable, called until it returns nil
+    Function(Value),
+}
+
+impl Iter {
+    pub fn new(value: Value) -> Result<Iter, String> {
+        match value {
+            Value::Range(a, b)      => Ok(Iter::Range(a, b)),
+            Value::StringLiteral(s) => Ok(Iter::Chars(s.chars().collect(), 0)),
+            Value::List(l)          => Ok(Iter::List(l, 0)),
+            Value::Map(m)           => Ok(Iter::Keys(m.borrow().keys().cloned().collect(), 0)),
+            f @ Value::Object(_)    => Ok(Iter::Function(f)),
+            v                       => Err(format!("can't iterate over '{}'", v)),
+        }
+    }
+}
@@ -1,15 +1,23 @@
 use std::collections::HashMap;
+use std::cell::RefCell;
+use std::rc::Rc;
 
 pub mod value;
 pub mod op;
+pub mod frame;
+pub mod iter;
 
 #[macro_use]
 pub mod object;
 
 pub use self::value::Value;
 pub use self::op::Op;
+pub use self::frame::Frame;
+pub use self::iter::Iter;
 pub use self::object::*;
 
+use lexer::TokenPosition;
+
 macro_rules! binary_op {
     ($vm_ref:expr, $a:ident, $b:ident, $r:expr) => {
         { let $b = $vm_ref.stack.pop().unwrap();
@@ -20,10 +28,16 @@ macro_rules! binary_op {
 }
 
 pub struct Machine {
-    program: Vec<Op>,
-    stack:   Vec<Value>,
+    program:   Vec<Op>,
+    stack:     Vec<Value>,
+    frame:     Option<Frame>,
+    iterators: Vec<Iter>,
+    // stack depths of the loops being run
+    loops:     Vec<usize>,
     pointer: usize,
     running: bool,
+    // of the statement being run, or of the one a failed call failed at
+    position: Option<TokenPosition>,
 }
 
 impl Machine {
@@ -31,14 +45,148 @@ impl Machine {
         Machine {
             program,
             stack: Vec::new(),
+            frame: None,
+            iterators: Vec::new(),
+            loops: Vec::new(),
+            pointer: 0,
+            running: false,
+            position: None,
+        }
+    }
+
+    // a function call, names are local to `frame` and shadow the globals
+    pub fn frame(program: Vec<Op>, frame: Frame) -> Machine {
+        Machine {
+            program,
+            stack: Vec::new(),
+            frame: Some(frame),
+            iterators: Vec::new(),
+            loops: Vec::new(),
             pointer: 0,
             running: false,
+            position: None,
+        }
+    }
+
+    // where the last error was raised
+    pub fn position(&self) -> Option<TokenPosition> {
+        self.position
+    }
+
+    fn lookup(&self, name: &str, globals: &HashMap<String, Value>) -> Option<Value> {
+        if let Some(ref frame) = self.frame {
+            if let Some(v) = frame.get(name) {
+                return Some(v)
+            }
+        }
+
+        globals.get(name).cloned()
+    }
+
+    fn define(&mut self, name: String, value: Value, globals: &mut HashMap<String, Value>) {
+        match self.frame {
+            Some(ref mut frame) => frame.define(name, value),
+            None                => { globals.insert(name, value); },
+        }
+    }
+
+    // new names are local to a call, existing globals are assigned like upvalues
+    fn assign(&mut self, name: String, value: Value, globals: &mut HashMap<String, Value>) {
+        match self.frame {
+            Some(ref mut frame) if frame.has(&name) || !globals.contains_key(&name) => frame.assign(name, value),
+            _ => { globals.insert(name, value); },
+        }
+    }
+
+    // globals are never captured, they are looked up when the closure runs
+    fn close(&mut self, function: Object, globals: &HashMap<String, Value>) -> Result<Object, String> {
+        match function {
+            Object::Function {args, body, locals, assigned, upvalues: names} => {
+                let mut upvalues = HashMap::new();
+
+                if let Some(ref mut frame) = self.frame {
+                    for name in names {
+                        if let Some(c) = frame.capture(&name, globals) {
+                            upvalues.insert(name, c);
+                        }
+                    }
+                }
+
+                Ok(Object::Closure {args, body, locals, assigned, upvalues})
+            },
+            _ => Err("very broken closure code?!".to_owned()),
+        }
+    }
+
+    fn call(&mut self, callee: Value, mut values: Vec<Value>, globals: &mut HashMap<String, Value>) -> Result<Value, String> {
+        let (args, body, mut frame) = match callee {
+            Value::Object(Object::Function {args, body, locals, assigned, ..}) => {
+                (args, body, Frame::new(HashMap::new(), locals, assigned))
+            },
+            Value::Object(Object::Closure {args, body, locals, assigned, upvalues}) => {
+                (args, body, Frame::new(upvalues, locals, assigned))
+            },
+            Value::Object(Object::Native(Native::Function(f))) => {
+                values.reverse();
+                return f(values)
+            },
+            s => return Err(format!("can't call '{}'", s)),
+        };
+
+        for arg in args {
+            frame.define(arg, values.pop().unwrap_or(Value::Nil));
         }
+
+        let mut machine = Machine::frame(body, frame);
+        let result      = machine.run(globals);
+
+        // errors point into the function raising them, unless its body is inline
+        if result.is_err() && machine.position.is_some() {
+            self.position = machine.position
+        }
+
+        Ok(try!(result).unwrap_or(Value::Nil))
     }
 
-    pub fn run(&mut self, scopes: &mut HashMap<String, Value>) -> Result<Option<Value>, String> {
+    // advances the innermost loop's iterator, `None` when it is exhausted
+    fn iterate(&mut self, globals: &mut HashMap<String, Value>) -> Result<Option<Value>, String> {
+        let function = match self.iterators.last_mut() {
+            Some(&mut Iter::Range(ref mut a, b)) => {
+                if *a >= b {
+                    return Ok(None)
+                }
+
+                *a += 1;
+                return Ok(Some(Value::IntLiteral(*a - 1)))
+            },
+            Some(&mut Iter::Chars(ref chars, ref mut i)) => {
+                *i += 1;
+                return Ok(chars.get(*i - 1).map(|c| Value::CharLiteral(*c)))
+            },
+            Some(&mut Iter::List(ref list, ref mut i)) => {
+                *i += 1;
+                return Ok(list.borrow().get(*i - 1).cloned())
+            },
+            Some(&mut Iter::Keys(ref keys, ref mut i)) => {
+                *i += 1;
+                return Ok(keys.get(*i - 1).cloned())
+            },
+            Some(&mut Iter::Function(ref f)) => f.clone(),
+            None => return Err("very broken iterator code?!".to_owned()),
+        };
+
+        match try!(self.call(function, Vec::new(), globals)) {
+            Value::Nil => Ok(None),
+            v          => Ok(Some(v)),
+        }
+    }
+
+    // the returned value, `None` when the program runs off its end
+    pub fn run(&mut self, globals: &mut HashMap<String, Value>) -> Result<Option<Value>, String> {
         self.running = true;
 
+        let mut returned = None;
+
         while self.running && self.pointer < self.program.len() {
             match self.program[self.pointer] {
                 Op::Value(ref v) => self.stack.push(v.clone()),
@@ -46,25 +194,106 @@ impl Machine {
                 Op::Sub      => binary_op!(self, a, b, try!(a.sub(b))),
                 Op::Mul      => binary_op!(self, a, b, try!(a.mul(b))),
                 Op::Div      => binary_op!(self, a, b, try!(a.div(b))),
+                Op::IntDiv   => binary_op!(self, a, b, try!(a.int_div(b))),
+                Op::Mod      => binary_op!(self, a, b, try!(a.rem(b))),
+                Op::Pow      => binary_op!(self, a, b, try!(a.pow(b))),
+                Op::BitAnd   => binary_op!(self, a, b, try!(a.bit_and(b))),
+                Op::BitOr    => binary_op!(self, a, b, try!(a.bit_or(b))),
+                Op::BitXor   => binary_op!(self, a, b, try!(a.bit_xor(b))),
+                Op::Shl      => binary_op!(self, a, b, try!(a.shl(b))),
+                Op::Shr      => binary_op!(self, a, b, try!(a.shr(b))),
+                Op::Neg      => {
+                    let a = try!(self.stack.pop().unwrap().neg());
+                    self.stack.push(a)
+                },
+                Op::Complement => {
+                    let a = try!(self.stack.pop().unwrap().complement());
+                    self.stack.push(a)
+                },
+                Op::Not      => {
+                    let a = self.stack.pop().unwrap().not();
+                    self.stack.push(a)
+                },
                 Op::Equals   => binary_op!(self, a, b, Value::BoolLiteral(a == b)),
                 Op::NEquals  => binary_op!(self, a, b, Value::BoolLiteral(a != b)),
                 Op::Lt       => binary_op!(self, a, b, Value::BoolLiteral(a < b)),
                 Op::LtEquals => binary_op!(self, a, b, Value::BoolLiteral(a <= b)),
                 Op::Gt       => binary_op!(self, a, b, Value::BoolLiteral(a > b)),
                 Op::GtEquals => binary_op!(self, a, b, Value::BoolLiteral(a >= b)),
+                Op::Range    => binary_op!(self, a, b, try!(a.range(b))),
+                Op::Iter     => {
+                    let iter = try!(Iter::new(self.stack.pop().unwrap()));
+                    self.iterators.push(iter)
+                },
+                Op::IterNext(n) => match try!(self.iterate(globals)) {
+                    Some(v) => self.stack.push(v),
+                    None    => {
+                        self.pointer = (self.pointer as i32 + n) as usize;
+                        continue
+                    },
+                },
+                Op::IterDrop => { self.iterators.pop(); },
+                Op::List(len) => {
+                    let at      = self.stack.len() - len;
+                    let content = self.stack.split_off(at);
+
+                    self.stack.push(Value::List(Rc::new(RefCell::new(content))))
+                },
+                Op::Interpolate(len) => {
+                    let at      = self.stack.len() - len;
+                    let content = self.stack.split_off(at);
+
+                    let s: Vec<String> = content.iter().map(|v| format!("{}", v)).collect();
+
+                    self.stack.push(Value::StringLiteral(s.concat()))
+                },
+                Op::Map(len) => {
+                    let at      = self.stack.len() - len * 2;
+                    let content = self.stack.split_off(at);
+
+                    let mut map = HashMap::new();
+
+                    for pair in content.chunks(2) {
+                        if !pair[0].is_hashable() {
+                            return Err(format!("can't use '{}' as key", pair[0]))
+                        }
+
+                        map.insert(pair[0].clone(), pair[1].clone());
+                    }
+
+                    self.stack.push(Value::Map(Rc::new(RefCell::new(map))))
+                },
+                Op::Index    => binary_op!(self, a, b, try!(a.index(b))),
+                Op::SetIndex => {
+                    let value = self.stack.pop().unwrap();
+                    let index = self.stack.pop().unwrap();
+
+                    try!(self.stack.pop().unwrap().set_index(index, value))
+                },
                 Op::Define   => match self.stack.pop().unwrap() {
-                    Value::StringLiteral(s) => { scopes.insert(s, self.stack.pop().unwrap()); },
+                    Value::StringLiteral(s) => {
+                        let value = self.stack.pop().unwrap();
+                        self.define(s, value, globals)
+                    },
+                    _                       => return Err("very broken assignment code?!".to_owned()),
+                },
+                Op::Assign   => match self.stack.pop().unwrap() {
+                    Value::StringLiteral(s) => {
+                        let value = self.stack.pop().unwrap();
+                        self.assign(s, value, globals)
+                    },
                     _                       => return Err("very broken assignment code?!".to_owned()),
                 },
-                Op::Name(ref n) => match scopes.get(n) {
-                    Some(v) => self.stack.push(v.clone()),
-                    None => (),
+                Op::Closure  => match self.stack.pop().unwrap() {
+                    Value::Object(o) => {
+                        let closure = try!(self.close(o, globals));
+                        self.stack.push(Value::Object(closure))
+                    },
+                    _                => return Err("very broken closure code?!".to_owned()),
                 },
-                Op::Name(ref n) => {
-                    match scopes.get(n) {
-                        Some(v) => self.stack.push(v.clone()),
-                        None    => (),
-                    }
+                Op::Name(ref n) => match self.lookup(n, globals) {
+                    Some(v) => self.stack.push(v),
+                    None    => return Err(format!("undefined name '{}'", n)),
                 },
                 Op::JumpUnless(ref n) => if !self.stack.pop().unwrap().to_boolean() {
                     self.pointer = (self.pointer as i32 + *n) as usize;
@@ -78,9 +307,31 @@ impl Machine {
                     self.pointer = (self.pointer as i32 + *n) as usize;
                     continue
                 },
-                Op::Return => self.running = false,
+                Op::Loop    => self.loops.push(self.stack.len()),
+                Op::EndLoop => { self.loops.pop(); },
+                Op::Unwind(ref n) => {
+                    let depth = *self.loops.last().unwrap();
+                    self.stack.truncate(depth);
+
+                    self.pointer = (self.pointer as i32 + *n) as usize;
+                    continue
+                },
+                Op::Break(_)    => return Err("'break' outside of loop".to_owned()),
+                Op::Continue(_) => return Err("'continue' outside of loop".to_owned()),
+                Op::Position(pos) => self.position = Some(pos),
+                Op::Pop      => { self.stack.pop(); },
+                Op::Duplicate(n) => {
+                    let at  = self.stack.len() - n;
+                    let top = self.stack[at ..].to_vec();
+
+                    self.stack.extend(top)
+                },
+                Op::Return => {
+                    returned     = self.stack.pop();
+                    self.running = false
+                },
                 Op::Call => {
-                    let mut len = match self.stack.pop().unwrap() {
+                    let len = match self.stack.pop().unwrap() {
                         Value::IntLiteral(n) => n as usize,
                         _ => panic!("very bad arg-len bytecode!"),
                     };
@@ -91,40 +342,10 @@ impl Machine {
                         values.push(self.stack.pop().unwrap())
                     }
 
+                    let callee = self.stack.pop().unwrap();
+                    let result = try!(self.call(callee, values, globals));
 
-                    match self.stack.pop().unwrap() {
-                        Value::Object(o) => {
-                            match o {
-                                Object::Function {args, body} => {
-                                    let mut frame = Machine::new(body);
-                                    for arg in args {
-                                        if len > 0 {
-                                            scopes.insert(arg.clone(), values.pop().unwrap());
-                                            len -= 1
-                                        } else {
-                                            scopes.insert(arg.clone(), Value::Nil);
-                                        }
-                                    }
-                                    match try!(frame.run(scopes)) {
-                                        Some(result) => self.stack.push(result),
-                                        None         => (),
-                                    }
-                                },
-                                Object::Native(nobj) => {
-                                   match nobj {
-                                      Native::Function(f) => {
-                                          values.reverse();
-                                          self.stack.push(f(values));
-                                      },
-                                   }
-                                },
-                            }
-                        },
-                        s => {
-                            println!("found '{}' on stack: {:#?}", s, self.stack);
-                            panic!("very invalid and broken call")
-                        },
-                    }
+                    self.stack.push(result)
                 },
                 _ => panic!("angery, not covered!?!"),
             }
@@ -133,6 +354,6 @@ impl Machine {
         }
 
         self.running = false;
-        Ok(self.stack.last().cloned())
+        Ok(returned)
     }
 }
\ No newline at end of file
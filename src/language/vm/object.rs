@@ -1,16 +1,32 @@
+use std::collections::HashMap;
+use std::cell::RefCell;
+use std::rc::Rc;
+
 use vm::{Op, Value};
 
+pub type Upvalue = Rc<RefCell<Value>>;
+
 #[derive(Debug, Clone)]
 pub enum Native {
-    Function(fn(Vec<Value>) -> Value),
+    Function(fn(Vec<Value>) -> Result<Value, String>),
 }
 
 #[derive(Debug, Clone)]
 pub enum Object {
     Native(Native),
     Function {
-        args: Vec<String>,
-        body: Vec<Op>,
+        args:     Vec<String>,
+        body:     Vec<Op>,
+        locals:   Vec<String>,
+        assigned: Vec<String>,
+        upvalues: Vec<String>,
+    },
+    Closure {
+        args:     Vec<String>,
+        body:     Vec<Op>,
+        locals:   Vec<String>,
+        assigned: Vec<String>,
+        upvalues: HashMap<String, Upvalue>,
     },
 }
 
@@ -1,5 +1,7 @@
 use vm::Value;
 
+use lexer::TokenPosition;
+
 #[derive(Debug, Clone)]
 pub enum Op {
     Value(Value),
@@ -7,17 +9,52 @@ pub enum Op {
     Sub,
     Mul,
     Div,
+    Mod,
+    Pow,
+    IntDiv,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    Neg,
+    Not,
+    Complement,
     Equals,
     NEquals,
     Lt,
     Gt,
     LtEquals,
     GtEquals,
+    Range,
     Return,
     Define,
+    Assign,
+    Closure,
     Call,
     JumpUnless(i32),
     JumpIf(i32),
     Jump(i32),
+    Iter,
+    IterNext(i32),
+    IterDrop,
+    // `Loop` records the stack depth `Unwind` goes back to, `EndLoop` forgets it
+    Loop,
+    EndLoop,
+    Break(TokenPosition),
+    Continue(TokenPosition),
+    // a resolved `break` or `continue`, drops what the loop body left and jumps
+    Unwind(i32),
+    // the statement being run, for runtime errors
+    Position(TokenPosition),
+    Pop,
+    // copies the top n values, e.g. the target and index of `a[i] += b`
+    Duplicate(usize),
     Name(String),
+    List(usize),
+    // joins the top n values as strings
+    Interpolate(usize),
+    Map(usize),
+    Index,
+    SetIndex,
 }
\ No newline at end of file
@@ -1,6 +1,10 @@
 use vm::Object;
 
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::cmp::Ordering;
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::fmt;
 
 #[derive(Debug, Clone)]
@@ -10,6 +14,9 @@ pub enum Value {
     StringLiteral(String),
     CharLiteral(char),
     BoolLiteral(bool),
+    Range(i64, i64),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<HashMap<Value, Value>>>),
     Object(Object),
     Nil,
 }
@@ -17,53 +24,238 @@ pub enum Value {
 #[allow(dead_code)]
 impl Value {
     pub fn add(&self, b: Value) -> Result<Value, String> {
-        Ok(match (self, b) {
-            (&Value::IntLiteral(a),   Value::IntLiteral(b))             => Value::IntLiteral(a + b),
-            (&Value::FloatLiteral(a), Value::FloatLiteral(b))           => Value::FloatLiteral(a + b),
-            (&Value::FloatLiteral(a), Value::IntLiteral(b))             => Value::FloatLiteral(a + (b as f64)),
-            (&Value::IntLiteral(a),   Value::FloatLiteral(b))           => Value::FloatLiteral((a as f64) + b),
-            (&Value::StringLiteral(ref a), Value::StringLiteral(ref b)) => Value::StringLiteral(a.clone() + b.as_str()),
-            (&Value::StringLiteral(ref a), Value::CharLiteral(ref b))   => Value::StringLiteral(a.clone() + &b.to_string()),
-            (&Value::CharLiteral(a),   Value::CharLiteral(b))           => Value::StringLiteral(format!("{}{}", a, b)),
-            (&Value::CharLiteral(a),   Value::StringLiteral(b))         => Value::StringLiteral(format!("{}{}", a, b)),
-
-            _ => return Err("invalid operation".to_owned())
+        Ok(match (self, &b) {
+            (&Value::IntLiteral(a),   &Value::IntLiteral(b))             => Value::IntLiteral(try!(a.checked_add(b).ok_or("integer overflow"))),
+            (&Value::FloatLiteral(a), &Value::FloatLiteral(b))           => Value::FloatLiteral(a + b),
+            (&Value::FloatLiteral(a), &Value::IntLiteral(b))             => Value::FloatLiteral(a + (b as f64)),
+            (&Value::IntLiteral(a),   &Value::FloatLiteral(b))           => Value::FloatLiteral((a as f64) + b),
+            (&Value::StringLiteral(ref a), &Value::StringLiteral(ref b)) => Value::StringLiteral(a.clone() + b.as_str()),
+            (&Value::StringLiteral(ref a), &Value::CharLiteral(ref b))   => Value::StringLiteral(a.clone() + &b.to_string()),
+            (&Value::CharLiteral(a),   &Value::CharLiteral(b))           => Value::StringLiteral(format!("{}{}", a, b)),
+            (&Value::CharLiteral(a),   &Value::StringLiteral(ref b))     => Value::StringLiteral(format!("{}{}", a, b)),
+
+            _ => return Err(operands("+", self, &b))
         })
     }
 
     pub fn sub(&self, b: Value) -> Result<Value, String> {
-        Ok(match (self, b) {
-            (&Value::IntLiteral(a),   Value::IntLiteral(b))   => Value::IntLiteral(a - b),
-            (&Value::FloatLiteral(a), Value::IntLiteral(b))   => Value::FloatLiteral(a - (b as f64)),
-            (&Value::IntLiteral(a), Value::FloatLiteral(b))   => Value::FloatLiteral((a as f64) - b),
-            (&Value::FloatLiteral(a), Value::FloatLiteral(b)) => Value::FloatLiteral(a - b),
+        Ok(match (self, &b) {
+            (&Value::IntLiteral(a),   &Value::IntLiteral(b))   => Value::IntLiteral(try!(a.checked_sub(b).ok_or("integer overflow"))),
+            (&Value::FloatLiteral(a), &Value::IntLiteral(b))   => Value::FloatLiteral(a - (b as f64)),
+            (&Value::IntLiteral(a), &Value::FloatLiteral(b))   => Value::FloatLiteral((a as f64) - b),
+            (&Value::FloatLiteral(a), &Value::FloatLiteral(b)) => Value::FloatLiteral(a - b),
 
-            _ => return Err("invalid operation".to_owned())
+            _ => return Err(operands("-", self, &b))
         })
     }
 
     pub fn mul(&self, b: Value) -> Result<Value, String> {
-        Ok(match (self, b) {
-            (&Value::IntLiteral(a),   Value::IntLiteral(b))   => Value::IntLiteral(a * b),
-            (&Value::FloatLiteral(a), Value::IntLiteral(b))   => Value::FloatLiteral(a * (b as f64)),
-            (&Value::IntLiteral(a), Value::FloatLiteral(b))   => Value::FloatLiteral((a as f64) * b),
-            (&Value::FloatLiteral(a), Value::FloatLiteral(b)) => Value::FloatLiteral(a * b),
+        Ok(match (self, &b) {
+            (&Value::IntLiteral(a),   &Value::IntLiteral(b))   => Value::IntLiteral(try!(a.checked_mul(b).ok_or("integer overflow"))),
+            (&Value::FloatLiteral(a), &Value::IntLiteral(b))   => Value::FloatLiteral(a * (b as f64)),
+            (&Value::IntLiteral(a), &Value::FloatLiteral(b))   => Value::FloatLiteral((a as f64) * b),
+            (&Value::FloatLiteral(a), &Value::FloatLiteral(b)) => Value::FloatLiteral(a * b),
 
-            _ => return Err("invalid operation".to_owned())
+            _ => return Err(operands("*", self, &b))
         })
     }
 
     pub fn div(&self, b: Value) -> Result<Value, String> {
+        if b.is_zero() {
+            return Err("division by zero".to_owned())
+        }
+
+        Ok(match (self, &b) {
+            (&Value::IntLiteral(a),   &Value::IntLiteral(b))   => Value::IntLiteral(try!(a.checked_div(b).ok_or("integer overflow"))),
+            (&Value::FloatLiteral(a), &Value::IntLiteral(b))   => Value::FloatLiteral(a / (b as f64)),
+            (&Value::IntLiteral(a), &Value::FloatLiteral(b))   => Value::FloatLiteral((a as f64) / b),
+            (&Value::FloatLiteral(a), &Value::FloatLiteral(b)) => Value::FloatLiteral(a / b),
+
+            _ => return Err(operands("/", self, &b))
+        })
+    }
+
+    // `//`, rounds towards negative infinity
+    pub fn int_div(&self, b: Value) -> Result<Value, String> {
+        if b.is_zero() {
+            return Err("division by zero".to_owned())
+        }
+
+        Ok(match (self, &b) {
+            (&Value::IntLiteral(a),   &Value::IntLiteral(b))   => {
+                let q = try!(a.checked_div(b).ok_or("integer overflow"));
+
+                if a % b != 0 && (a < 0) != (b < 0) {
+                    Value::IntLiteral(q - 1)
+                } else {
+                    Value::IntLiteral(q)
+                }
+            },
+            (&Value::FloatLiteral(a), &Value::IntLiteral(b))   => Value::FloatLiteral((a / (b as f64)).floor()),
+            (&Value::IntLiteral(a), &Value::FloatLiteral(b))   => Value::FloatLiteral(((a as f64) / b).floor()),
+            (&Value::FloatLiteral(a), &Value::FloatLiteral(b)) => Value::FloatLiteral((a / b).floor()),
+
+            _ => return Err(operands("//", self, &b))
+        })
+    }
+
+    // `%`, takes the sign of the divisor so `a == (a // b) * b + a % b`
+    pub fn rem(&self, b: Value) -> Result<Value, String> {
+        if b.is_zero() {
+            return Err("modulo by zero".to_owned())
+        }
+
+        Ok(match (self, &b) {
+            (&Value::IntLiteral(a),   &Value::IntLiteral(b))   => {
+                let r = a.checked_rem(b).unwrap_or(0);
+
+                if r != 0 && (r < 0) != (b < 0) {
+                    Value::IntLiteral(r + b)
+                } else {
+                    Value::IntLiteral(r)
+                }
+            },
+            (&Value::FloatLiteral(a), &Value::IntLiteral(b))   => Value::FloatLiteral(float_rem(a, b as f64)),
+            (&Value::IntLiteral(a), &Value::FloatLiteral(b))   => Value::FloatLiteral(float_rem(a as f64, b)),
+            (&Value::FloatLiteral(a), &Value::FloatLiteral(b)) => Value::FloatLiteral(float_rem(a, b)),
+
+            _ => return Err(operands("%", self, &b))
+        })
+    }
+
+    // integer powers stay integers unless the exponent is negative
+    pub fn pow(&self, b: Value) -> Result<Value, String> {
+        Ok(match (self, &b) {
+            (&Value::IntLiteral(a),   &Value::IntLiteral(b)) if b >= 0 => {
+                if b > u32::max_value() as i64 {
+                    return Err("integer overflow".to_owned())
+                }
+
+                Value::IntLiteral(try!(a.checked_pow(b as u32).ok_or("integer overflow")))
+            },
+            (&Value::IntLiteral(a),   &Value::IntLiteral(b))   => Value::FloatLiteral((a as f64).powf(b as f64)),
+            (&Value::FloatLiteral(a), &Value::IntLiteral(b))   => Value::FloatLiteral(a.powf(b as f64)),
+            (&Value::IntLiteral(a), &Value::FloatLiteral(b))   => Value::FloatLiteral((a as f64).powf(b)),
+            (&Value::FloatLiteral(a), &Value::FloatLiteral(b)) => Value::FloatLiteral(a.powf(b)),
+
+            _ => return Err(operands("**", self, &b))
+        })
+    }
+
+    fn is_zero(&self) -> bool {
+        match *self {
+            Value::IntLiteral(a)   => a == 0,
+            Value::FloatLiteral(a) => a == 0.0,
+            _                      => false,
+        }
+    }
+
+    pub fn neg(&self) -> Result<Value, String> {
+        Ok(match *self {
+            Value::IntLiteral(a)   => match a.checked_neg() {
+                Some(a) => Value::IntLiteral(a),
+                None    => return Err(format!("negating {} overflows", a)),
+            },
+            Value::FloatLiteral(a) => Value::FloatLiteral(-a),
+
+            _ => return Err(format!("can't negate '{}'", self))
+        })
+    }
+
+    pub fn complement(&self) -> Result<Value, String> {
+        match *self {
+            Value::IntLiteral(a) => Ok(Value::IntLiteral(!a)),
+
+            _ => Err(format!("can't complement '{}'", self))
+        }
+    }
+
+    pub fn bit_and(&self, b: Value) -> Result<Value, String> {
+        let (a, b) = try!(integers("&", self, &b));
+        Ok(Value::IntLiteral(a & b))
+    }
+
+    pub fn bit_or(&self, b: Value) -> Result<Value, String> {
+        let (a, b) = try!(integers("|", self, &b));
+        Ok(Value::IntLiteral(a | b))
+    }
+
+    pub fn bit_xor(&self, b: Value) -> Result<Value, String> {
+        let (a, b) = try!(integers("^", self, &b));
+        Ok(Value::IntLiteral(a ^ b))
+    }
+
+    pub fn shl(&self, b: Value) -> Result<Value, String> {
+        let (a, b) = try!(integers("<<", self, &b));
+        Ok(Value::IntLiteral(a << try!(shift(b))))
+    }
+
+    // arithmetic, keeps the sign
+    pub fn shr(&self, b: Value) -> Result<Value, String> {
+        let (a, b) = try!(integers(">>", self, &b));
+        Ok(Value::IntLiteral(a >> try!(shift(b))))
+    }
+
+    pub fn not(&self) -> Value {
+        Value::BoolLiteral(!self.to_boolean())
+    }
+
+    pub fn range(&self, b: Value) -> Result<Value, String> {
         Ok(match (self, b) {
-            (&Value::IntLiteral(a),   Value::IntLiteral(b))   => Value::IntLiteral(a / b),
-            (&Value::FloatLiteral(a), Value::IntLiteral(b))   => Value::FloatLiteral(a / (b as f64)),
-            (&Value::IntLiteral(a), Value::FloatLiteral(b))   => Value::FloatLiteral((a as f64) / b),
-            (&Value::FloatLiteral(a), Value::FloatLiteral(b)) => Value::FloatLiteral(a / b),
+            (&Value::IntLiteral(a), Value::IntLiteral(b)) => Value::Range(a, b),
 
-            _ => return Err("invalid operation".to_owned())
+            _ => return Err("range bounds must be integers".to_owned())
         })
     }
 
+    pub fn index(&self, i: Value) -> Result<Value, String> {
+        match *self {
+            Value::List(ref l) => {
+                let l = l.borrow();
+                let i = try!(position(&i, l.len()));
+
+                Ok(l[i].clone())
+            },
+            Value::Map(ref m) => Ok(m.borrow().get(&i).cloned().unwrap_or(Value::Nil)),
+
+            _ => Err(format!("can't index '{}'", self))
+        }
+    }
+
+    pub fn set_index(&self, i: Value, v: Value) -> Result<(), String> {
+        match *self {
+            Value::List(ref l) => {
+                let mut l = l.borrow_mut();
+                let i     = try!(position(&i, l.len()));
+
+                l[i] = v;
+                Ok(())
+            },
+            Value::Map(ref m) => {
+                if !i.is_hashable() {
+                    return Err(format!("can't use '{}' as key", i))
+                }
+
+                m.borrow_mut().insert(i, v);
+                Ok(())
+            },
+
+            _ => Err(format!("can't index '{}'", self))
+        }
+    }
+
+    // the values that can be used as map keys
+    pub fn is_hashable(&self) -> bool {
+        match *self {
+            Value::IntLiteral(_)
+            | Value::StringLiteral(_)
+            | Value::CharLiteral(_)
+            | Value::BoolLiteral(_)
+            | Value::Nil => true,
+            _            => false,
+        }
+    }
+
     pub fn to_boolean(&self) -> bool {
         match *self {
             Value::IntLiteral(v)        => v != 0,
@@ -71,12 +263,62 @@ impl Value {
             Value::StringLiteral(ref v) => v.len() > 0,
             Value::BoolLiteral(v)    => v,
             Value::CharLiteral(_)       => true,
+            Value::Range(a, b)          => a < b,
+            Value::List(ref l)          => l.borrow().len() > 0,
+            Value::Map(ref m)           => m.borrow().len() > 0,
             Value::Object(_)            => true,
             Value::Nil            => false,
         }
     }
 }
 
+fn operands(op: &str, a: &Value, b: &Value) -> String {
+    format!("'{}' can't be used on '{}' and '{}'", op, a, b)
+}
+
+fn integers(op: &str, a: &Value, b: &Value) -> Result<(i64, i64), String> {
+    match (a, b) {
+        (&Value::IntLiteral(a), &Value::IntLiteral(b)) => Ok((a, b)),
+
+        _ => Err(format!("'{}' expects integers, found '{}' and '{}'", op, a, b))
+    }
+}
+
+fn shift(n: i64) -> Result<u32, String> {
+    if n < 0 || n >= 64 {
+        Err(format!("can't shift by {}", n))
+    } else {
+        Ok(n as u32)
+    }
+}
+
+fn float_rem(a: f64, b: f64) -> f64 {
+    let r = a % b;
+
+    if r != 0.0 && (r < 0.0) != (b < 0.0) {
+        r + b
+    } else {
+        r
+    }
+}
+
+// negative indices count from the back
+fn position(i: &Value, len: usize) -> Result<usize, String> {
+    match *i {
+        Value::IntLiteral(i) => {
+            let at = if i < 0 { i + len as i64 } else { i };
+
+            if at < 0 || at >= len as i64 {
+                Err(format!("index {} out of range for length {}", i, len))
+            } else {
+                Ok(at as usize)
+            }
+        },
+
+        _ => Err(format!("can't index with '{}'", i))
+    }
+}
+
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -85,6 +327,15 @@ impl fmt::Display for Value {
             Value::BoolLiteral(ref b)   => write!(f, "{}", b),
             Value::StringLiteral(ref s) => write!(f, "{}", s),
             Value::CharLiteral(ref c)   => write!(f, "{}", c),
+            Value::Range(a, b)          => write!(f, "{}..{}", a, b),
+            Value::List(ref l)          => {
+                let s: Vec<String> = l.borrow().iter().map(|v| format!("{}", v)).collect();
+                write!(f, "[{}]", s.join(", "))
+            },
+            Value::Map(ref m)           => {
+                let s: Vec<String> = m.borrow().iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+                write!(f, "{{{}}}", s.join(", "))
+            },
             Value::Object(_)            => write!(f, "[object]"),
             Value::Nil                  => write!(f, "nil"),
         }
@@ -119,12 +370,33 @@ impl PartialEq for Value {
             },
             (&Value::BoolLiteral(ref a), &Value::BoolLiteral(ref b)) => a == b,
             (&Value::StringLiteral(ref a), &Value::StringLiteral(ref b)) => a == b,
+            (&Value::CharLiteral(a), &Value::CharLiteral(b)) => a == b,
+            (&Value::Range(a, b), &Value::Range(c, d)) => a == c && b == d,
+            (&Value::List(ref a), &Value::List(ref b)) => Rc::ptr_eq(a, b) || *a.borrow() == *b.borrow(),
+            (&Value::Map(ref a), &Value::Map(ref b))   => Rc::ptr_eq(a, b) || *a.borrow() == *b.borrow(),
             (&Value::Nil, &Value::Nil) => true,
             _ => false,
         }
     }
 }
 
+// needed for map keys, only `is_hashable` values are ever used as keys
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match *self {
+            Value::IntLiteral(a)        => a.hash(state),
+            // whole floats equal the int they hold
+            Value::FloatLiteral(a) if a.fract() == 0.0 => (a as i64).hash(state),
+            Value::StringLiteral(ref s) => s.hash(state),
+            Value::CharLiteral(c)       => c.hash(state),
+            Value::BoolLiteral(b)       => b.hash(state),
+            _                           => (),
+        }
+    }
+}
+
 impl PartialOrd for Value {
     fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
         if *self == *other {
@@ -1,6 +1,7 @@
 mod language;
 
 use language::syntax;
+use language::diagnostic;
 use language::vm;
 use language::compiler;
 use language::natives;
@@ -15,6 +16,8 @@ use parser::{Traveler, Parser};
 
 use vm::Machine;
 
+use diagnostic::{Diagnostic, Span};
+
 use std::io;
 use std::io::prelude::*;
 
@@ -42,15 +45,39 @@ fn repl() {
                     std::process::exit(0)
                 }
 
-                let lexer = lexer(&mut input_line.chars());
+                let tokens: Vec<_> = lexer(&mut input_line.chars()).collect();
+
+                let diagnostics = lexer::diagnostics(&tokens);
 
-                let traveler = Traveler::new(lexer.collect());
+                if !diagnostics.is_empty() {
+                    report(&diagnostics, &input_line);
+                    continue
+                }
+
+                let traveler = Traveler::new(tokens);
                 let mut parser = Parser::new(traveler);
 
-                let stack = compiler::statements(parser.parse());
+                let statements = match parser.parse() {
+                    Ok(statements)   => statements,
+                    Err(diagnostics) => {
+                        report(&diagnostics, &input_line);
+                        continue
+                    },
+                };
+
+                let stack = match compiler::compile(statements) {
+                    Ok(stack)        => stack,
+                    Err(diagnostics) => {
+                        report(&diagnostics, &input_line);
+                        continue
+                    },
+                };
+
                 let mut vm = Machine::new(stack);
 
-                vm.run(&mut scopes);
+                if let Err(e) = vm.run(&mut scopes) {
+                    report(&[runtime(&e, &vm, &input_line)], &input_line)
+                }
             }
 
             Err(e) => panic!(e),
@@ -58,40 +85,114 @@ fn repl() {
     }
 }
 
-fn test() {
+// runs a script, reporting any problems against its source
+fn run(path: &str, tab_width: Option<usize>) -> bool {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e)     => {
+            eprintln!("can't read '{}': {}", path, e);
+            return false
+        },
+    };
+
     let mut scopes = HashMap::new();
     natives::apply(&mut scopes);
 
-    let mut test = r#"
-fun fib(a)
-  if a < 3
-    return a
-  else
-    other_a?  = a - 1
-    other_a2? = a - 2
-    return fib(other_a?) + fib(other_a2?)
+    let mut tree = block_tree::BlockTree::new(&source, 0);
+
+    if let Some(w) = tab_width {
+        tree = tree.tab_width(w)
+    }
 
-putsln(fib(5))
-    "#;
+    if let Err(diagnostics) = tree.validate() {
+        report(&diagnostics, &source);
+        return false
+    }
 
-    let mut tree = block_tree::BlockTree::new(test, 0);
-    let indents  = &tree.collect_indents();
-    
-    let root = tree.tree(indents);
+    let indents = &tree.collect_indents();
+    let root    = tree.tree(indents);
+    let tokens  = process_branch(&root);
+
+    let diagnostics = lexer::diagnostics(&tokens);
+
+    if !diagnostics.is_empty() {
+        report(&diagnostics, &source);
+        return false
+    }
 
-    let lexer = process_branch(&root);
+    let mut parser = Parser::new(Traveler::new(tokens));
 
-    let traveler = Traveler::new(lexer);
-    let mut parser = Parser::new(traveler);
+    let statements = match parser.parse() {
+        Ok(statements)   => statements,
+        Err(diagnostics) => {
+            report(&diagnostics, &source);
+            return false
+        },
+    };
 
-    let p = parser.parse();
+    let stack = match compiler::compile(statements) {
+        Ok(stack)        => stack,
+        Err(diagnostics) => {
+            report(&diagnostics, &source);
+            return false
+        },
+    };
 
-    let stack = compiler::statements(p);
     let mut vm = Machine::new(stack);
 
-    vm.run(&mut scopes);
+    match vm.run(&mut scopes) {
+        Ok(_)  => true,
+        Err(e) => {
+            report(&[runtime(&e, &vm, &source)], &source);
+            false
+        },
+    }
 }
 
+// a runtime error, underlining the statement it was raised at
+fn runtime(e: &str, vm: &Machine, source: &str) -> Diagnostic {
+    let d = Diagnostic::error("E0300", e);
+
+    match vm.position() {
+        Some(pos) => {
+            let line = source.lines().nth(pos.line - 1).unwrap_or("");
+            let len  = line.chars().skip(pos.col).collect::<String>().trim_end().chars().count();
+
+            d.primary(Span::new(pos, len), "")
+        },
+        None => d,
+    }
+}
+
+fn report(diagnostics: &[Diagnostic], source: &str) {
+    for d in diagnostics {
+        eprintln!("{}", d.render(source))
+    }
+}
+
+// `ketchup [--tab-width n] [script]`
 fn main() {
-    repl()
+    let mut args      = std::env::args().skip(1);
+    let mut tab_width = None;
+    let mut path      = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tab-width" => match args.next().and_then(|w| w.parse::<usize>().ok()) {
+                Some(w) if w > 0 => tab_width = Some(w),
+                _ => {
+                    eprintln!("--tab-width expects a positive number");
+                    std::process::exit(2)
+                },
+            },
+            _ => path = Some(arg),
+        }
+    }
+
+    match path {
+        Some(path) => if !run(&path, tab_width) {
+            std::process::exit(1)
+        },
+        None => repl(),
+    }
 }
\ No newline at end of file
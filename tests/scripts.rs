@@ -0,0 +1,83 @@
+// runs every `tests/scripts/*.k` and compares what it prints with the `.stdout`
+// and `.stderr` files next to it, a missing file means nothing is printed there
+// and a script is expected to fail exactly when it prints errors. a `.args` file
+// holds arguments passed before the script
+//
+// `KETCHUP_BLESS=1 cargo test` writes the current output as the expected one
+
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap_or_default()
+}
+
+fn bless(path: &Path, content: &str) {
+    if content.is_empty() {
+        let _ = fs::remove_file(path);
+    } else {
+        fs::write(path, content).unwrap()
+    }
+}
+
+fn scripts(dir: &Path) -> Vec<PathBuf> {
+    let mut scripts: Vec<PathBuf> = fs::read_dir(dir).unwrap()
+                                      .map(|e| e.unwrap().path())
+                                      .filter(|p| p.extension() == Some(OsStr::new("k")))
+                                      .collect();
+    scripts.sort();
+    scripts
+}
+
+#[test]
+fn scripts_match_their_output() {
+    let dir      = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("scripts");
+    let blessing = env::var_os("KETCHUP_BLESS").is_some();
+    let scripts  = scripts(&dir);
+
+    assert!(!scripts.is_empty(), "no scripts in {}", dir.display());
+
+    let mut failures = Vec::new();
+
+    for script in &scripts {
+        let args = read(&script.with_extension("args"));
+
+        let output = Command::new(env!("CARGO_BIN_EXE_ketchup"))
+                         .args(args.split_whitespace())
+                         .arg(script)
+                         .env("RUST_BACKTRACE", "0")
+                         .output()
+                         .unwrap();
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        if blessing {
+            bless(&script.with_extension("stdout"), &stdout);
+            bless(&script.with_extension("stderr"), &stderr);
+            continue
+        }
+
+        let expected_stdout = read(&script.with_extension("stdout"));
+        let expected_stderr = read(&script.with_extension("stderr"));
+
+        let name = script.file_name().unwrap().to_string_lossy();
+
+        if stdout != expected_stdout {
+            failures.push(format!("{}: stdout\n--- expected\n{}--- found\n{}", name, expected_stdout, stdout))
+        }
+
+        if stderr != expected_stderr {
+            failures.push(format!("{}: stderr\n--- expected\n{}--- found\n{}", name, expected_stderr, stderr))
+        }
+
+        if output.status.success() != expected_stderr.is_empty() {
+            failures.push(format!("{}: exited with {}", name, output.status))
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}